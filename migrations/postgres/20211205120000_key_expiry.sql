@@ -0,0 +1,4 @@
+ALTER TABLE "databases" ADD COLUMN "expires_at" BIGINT;
+CREATE INDEX IF NOT EXISTS "databases_expiry_idx" ON "databases" (
+	"expires_at"
+);
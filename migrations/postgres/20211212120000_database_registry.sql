@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS "database_registry" (
+	"guild_id"	TEXT NOT NULL,
+	"name"	TEXT NOT NULL,
+	"created_at"	BIGINT NOT NULL,
+	"description"	TEXT,
+	PRIMARY KEY("guild_id", "name")
+);
+INSERT INTO "database_registry" ("guild_id", "name", "created_at")
+	SELECT DISTINCT "guild_id", "name", CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT) FROM "databases"
+	ON CONFLICT DO NOTHING;
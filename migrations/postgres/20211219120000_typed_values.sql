@@ -0,0 +1,12 @@
+ALTER TABLE "databases" ADD COLUMN "value_type" TEXT NOT NULL DEFAULT 'string';
+ALTER TABLE "databases" ADD COLUMN "number_value" DOUBLE PRECISION;
+-- Untyped values are taken as numbers only when written exactly like the bot writes them, see infer_stored
+UPDATE "databases" SET "value_type" = 'integer', "number_value" = CAST("key_value" AS DOUBLE PRECISION)
+	WHERE CASE WHEN "key_value" ~ '^(0|-?[1-9][0-9]{0,18})$' THEN CAST("key_value" AS NUMERIC) BETWEEN -9223372036854775808 AND 9223372036854775807 ELSE FALSE END;
+UPDATE "databases" SET "value_type" = 'float', "number_value" = CAST("key_value" AS DOUBLE PRECISION)
+	WHERE CASE WHEN "value_type" = 'string' AND "key_value" ~ '^-?(0|[1-9][0-9]*)\.[0-9]*[1-9]$' THEN length(ltrim(replace(replace("key_value", '-', ''), '.', ''), '0')) <= 15 AND CAST(CAST("key_value" AS DOUBLE PRECISION) AS TEXT) = "key_value" ELSE FALSE END;
+CREATE INDEX IF NOT EXISTS "databases_number_idx" ON "databases" (
+	"guild_id",
+	"name",
+	"number_value"
+);
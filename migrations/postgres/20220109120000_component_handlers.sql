@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS "component_handlers" (
+	"guild_id"	TEXT NOT NULL,
+	"custom_id"	TEXT NOT NULL,
+	"code"	TEXT NOT NULL,
+	"created_at"	BIGINT NOT NULL,
+	PRIMARY KEY("guild_id", "custom_id")
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS "custom_slash_commands" (
+	"guild_id"	TEXT NOT NULL,
+	"name"	TEXT NOT NULL,
+	"description"	TEXT NOT NULL,
+	"options"	TEXT NOT NULL,
+	"code"	TEXT NOT NULL,
+	"created_at"	BIGINT NOT NULL,
+	PRIMARY KEY("guild_id", "name")
+);
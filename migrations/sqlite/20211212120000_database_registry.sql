@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS "database_registry" (
+	"guild_id"	TEXT NOT NULL COLLATE BINARY,
+	"name"	TEXT NOT NULL,
+	"created_at"	INTEGER NOT NULL,
+	"description"	TEXT,
+	PRIMARY KEY("guild_id", "name")
+);
+INSERT OR IGNORE INTO "database_registry" ("guild_id", "name", "created_at")
+	SELECT DISTINCT "guild_id", "name", CAST(strftime('%s', 'now') AS INTEGER) FROM "databases";
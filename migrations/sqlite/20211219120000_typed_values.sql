@@ -0,0 +1,12 @@
+ALTER TABLE "databases" ADD COLUMN "value_type" TEXT NOT NULL DEFAULT 'string';
+ALTER TABLE "databases" ADD COLUMN "number_value" REAL;
+-- Untyped values are taken as numbers only when written exactly like the bot writes them, see infer_stored
+UPDATE "databases" SET "value_type" = 'integer', "number_value" = CAST("key_value" AS INTEGER)
+	WHERE CAST(CAST("key_value" AS INTEGER) AS TEXT) = "key_value";
+UPDATE "databases" SET "value_type" = 'float', "number_value" = CAST("key_value" AS REAL)
+	WHERE "value_type" = 'string' AND "key_value" GLOB '*.*[1-9]' AND "key_value" NOT GLOB '*[eE]*' AND CAST(CAST("key_value" AS REAL) AS TEXT) = "key_value";
+CREATE INDEX IF NOT EXISTS "databases_number_idx" ON "databases" (
+	"guild_id",
+	"name",
+	"number_value"
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS "scheduled_deletions" (
+	"id"	INTEGER PRIMARY KEY AUTOINCREMENT,
+	"channel_id"	TEXT NOT NULL,
+	"message_id"	TEXT NOT NULL,
+	"delete_at"	INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "scheduled_deletions_time_idx" ON "scheduled_deletions" (
+	"delete_at"
+);
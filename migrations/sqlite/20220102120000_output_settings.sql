@@ -0,0 +1,5 @@
+ALTER TABLE "config" ADD COLUMN "allow_everyone" BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE "config" ADD COLUMN "allow_roles" BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE "config" ADD COLUMN "allow_users" BOOLEAN NOT NULL DEFAULT 1;
+ALTER TABLE "config" ADD COLUMN "ping_on_reply" BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE "config" ADD COLUMN "reply_to_trigger" BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS "modals" (
+	"guild_id"	TEXT NOT NULL COLLATE BINARY,
+	"modal_id"	TEXT NOT NULL,
+	"title"	TEXT NOT NULL,
+	"inputs"	TEXT NOT NULL,
+	"code"	TEXT NOT NULL,
+	"created_at"	INTEGER NOT NULL,
+	PRIMARY KEY("guild_id", "modal_id")
+);
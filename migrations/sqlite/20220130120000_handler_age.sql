@@ -0,0 +1,6 @@
+CREATE INDEX IF NOT EXISTS "component_handlers_age_idx" ON "component_handlers" (
+	"created_at"
+);
+CREATE INDEX IF NOT EXISTS "modals_age_idx" ON "modals" (
+	"created_at"
+);
@@ -3,222 +3,386 @@ use serenity::{
 	async_trait,
 	prelude::Context,
 };
-use yttrium_key_base::environment::{
-	Environment,
-	events,
-};
-use yttrium::{
-	ResultAndWarnings,
-	errors_and_warns::Error
+use yttrium_key_base::environment::events;
+use serenity::model::interactions::{
+	Interaction,
+	InteractionResponseType,
+	message_component::{
+		InteractionMessage,
+		MessageComponentInteraction,
+	},
+	modal::ModalSubmitInteraction,
 };
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::utilities;
+use crate::components;
+use crate::slash_commands;
+use crate::diffs;
 use crate::types::*;
-use crate::databases::*;
+use crate::storage::Storage;
 
-async fn get_event_code(event_name: &str, guild_id: &str, pool: &sqlx::SqlitePool) -> Option<String> {
-	let query = sqlx::query!("SELECT code FROM events WHERE event = ? AND guild_id = ?", event_name, guild_id);
-	match query.fetch_optional(pool).await {
-		Ok(Some(result)) => {
-			return Some(result.code);
+async fn get_event_code(event_name: &str, guild_id: &str, storage: &Arc<dyn Storage>) -> Option<String> {
+	match storage.get_event(guild_id, event_name).await {
+		Ok(code) => {
+			return code;
 		}
-		Ok(None) => {
+		Err(error) => {
+			tracing::error!("get_event_code: DB error with event: `{}` on guild `{}`: `{}`", event_name, guild_id, error);
 			return None;
 		}
+	}
+}
+
+/// Runs the code registered for `event_name` in the guild, if there is any
+async fn run_event(event_name: &'static str, guild_id: serenity::model::id::GuildId, event_info: events::EventType, context: &Context) {
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	let code = get_event_code(event_name, &guild_id.to_string(), db).await;
+	drop(lock);
+	if let Some(code) = code {
+		utilities::run_script(context, code, event_info, guild_id, None, move |context, error| async move {
+			utilities::report_error(&context, guild_id, &format!("Error in the `{}` event: `{:#?}`", event_name, error)).await;
+		}).await;
+	}
+}
+
+/// Runs the code for a used button or select menu
+/// A modal with the same custom ID opens instead of running anything
+/// The handler stored with the component comes first, the guild's `ComponentInteraction` event is the fallback
+/// The interaction is acknowledged before the code runs, so that Discord doesn't show it as failed while the script is working
+async fn run_component_interaction(context: &Context, interaction: MessageComponentInteraction) {
+	let guild_id;
+	match interaction.guild_id {
+		Some(id) => {
+			guild_id = id;
+		}
+		None => {
+			return;
+		}
+	}
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	match db.get_modal(&guild_id.to_string(), &interaction.data.custom_id).await {
+		Ok(Some(modal)) => {
+			//Opening a modal has to be the first response to the interaction, so nothing else can happen here
+			if let Err(error) = components::open_modal(context, &interaction, &modal).await {
+				utilities::report_error(context, guild_id, &format!("Could not open modal `{}`: {}", modal.modal_id, error)).await;
+			}
+			return;
+		}
+		Ok(None) => {}
 		Err(error) => {
-			eprintln!("get_event_code: DB error with event: `{}` on guild `{}`: `{}`", event_name, guild_id, error);
-			return None;
+			tracing::error!("run_component_interaction: DB error with modal: `{}` on guild `{}`: `{}`", interaction.data.custom_id, guild_id, error);
+			return;
+		}
+	}
+	let code;
+	match db.find_component_handler(&guild_id.to_string(), &interaction.data.custom_id).await {
+		Ok(Some(handler)) => {
+			code = handler;
+		}
+		Ok(None) => {
+			match get_event_code("ComponentInteraction", &guild_id.to_string(), db).await {
+				Some(event_code) => {
+					code = event_code;
+				}
+				None => {
+					return;
+				}
+			}
+		}
+		Err(error) => {
+			tracing::error!("run_component_interaction: DB error with component: `{}` on guild `{}`: `{}`", interaction.data.custom_id, guild_id, error);
+			return;
 		}
 	}
+	let acknowledged = interaction.create_interaction_response(context, |response| {
+		return response.kind(InteractionResponseType::DeferredUpdateMessage);
+	}).await;
+	if let Err(error) = acknowledged {
+		tracing::warn!("Could not acknowledge the use of component `{}`: `{}`", interaction.data.custom_id, error);
+	}
+	//Ephemeral messages can't be fetched or edited through the channel, so they can't be the origin
+	let origin;
+	let message_id;
+	match &interaction.message {
+		InteractionMessage::Regular(message) => {
+			origin = Some(message.clone());
+			message_id = message.id;
+		}
+		InteractionMessage::Ephemeral(message) => {
+			origin = None;
+			message_id = message.id;
+		}
+	}
+	drop(lock);
+	let custom_id = interaction.data.custom_id.clone();
+	let event_info = events::EventType::ComponentInteraction(events::ComponentInteractionEventInfo::new(interaction.channel_id, message_id, interaction.user.id, interaction.data.custom_id.clone(), interaction.data.values.clone()));
+	utilities::run_script(context, code, event_info, guild_id, origin, move |context, error| async move {
+		utilities::report_error(&context, guild_id, &format!("Error in the handler of component `{}`: `{:#?}`", custom_id, error)).await;
+	}).await;
 }
 
-async fn send_output(event_name: &str, context: &Context, output: Result<ResultAndWarnings<'_, SqlDatabaseManager, SqlDatabase>, Error>) {
-	match output {
-		Ok(output) => {
-			utilities::send_result(context, output).await;
+/// Runs the code stored with a submitted modal, with the entered text in the environment
+async fn run_modal_submit(context: &Context, interaction: ModalSubmitInteraction) {
+	let guild_id;
+	match interaction.guild_id {
+		Some(id) => {
+			guild_id = id;
+		}
+		None => {
+			return;
+		}
+	}
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	let modal;
+	match db.get_modal(&guild_id.to_string(), &interaction.data.custom_id).await {
+		Ok(Some(found)) => {
+			modal = found;
+		}
+		Ok(None) => {
+			return;
 		}
 		Err(error) => {
-			unimplemented!("Error in {}: `{:#?}`", event_name, error);
+			tracing::error!("run_modal_submit: DB error with modal: `{}` on guild `{}`: `{}`", interaction.data.custom_id, guild_id, error);
+			return;
 		}
 	}
+	let acknowledged = interaction.create_interaction_response(context, |response| {
+		return response.kind(InteractionResponseType::DeferredUpdateMessage);
+	}).await;
+	if let Err(error) = acknowledged {
+		tracing::warn!("Could not acknowledge the submission of modal `{}`: `{}`", modal.modal_id, error);
+	}
+	drop(lock);
+	let values = components::submitted_values(&interaction);
+	let event_info = events::EventType::ModalSubmit(events::ModalSubmitEventInfo::new(interaction.channel_id, interaction.user.id, modal.modal_id.clone(), values));
+	let modal_id = modal.modal_id;
+	//The message with the button that opened the modal is the origin, so `update` can edit it
+	utilities::run_script(context, modal.code, event_info, guild_id, interaction.message.clone(), move |context, error| async move {
+		utilities::report_error(&context, guild_id, &format!("Error in the handler of modal `{}`: `{:#?}`", modal_id, error)).await;
+	}).await;
 }
 
-pub struct Handler;
+/// Works out which voice events a single voice state update corresponds to
+/// `VoiceUpdate` is always the last one, so that existing handlers keep firing on every change
+fn voice_event_names(old: Option<&serenity::model::prelude::VoiceState>, new: &serenity::model::prelude::VoiceState) -> Vec<&'static str> {
+	let mut names = Vec::new();
+	let old_channel = old.and_then(|state| state.channel_id);
+	match (old_channel, new.channel_id) {
+		(None, Some(_)) => {
+			names.push("VoiceJoin");
+		}
+		(Some(_), None) => {
+			names.push("VoiceLeave");
+		}
+		(Some(old_id), Some(new_id)) if old_id != new_id => {
+			names.push("VoiceMove");
+		}
+		(Some(_), Some(_)) => {
+			//Same channel, so something about the user's state has changed
+			let old = old.unwrap();
+			if old.mute != new.mute || old.self_mute != new.self_mute {
+				names.push("VoiceMute");
+			}
+			if old.deaf != new.deaf || old.self_deaf != new.self_deaf {
+				names.push("VoiceDeafen");
+			}
+			if old.self_stream.unwrap_or(false) != new.self_stream.unwrap_or(false) {
+				names.push("VoiceStream");
+			}
+		}
+		(None, None) => {}
+	}
+	names.push("VoiceUpdate");
+	return names;
+}
+
+#[derive(Default)]
+pub struct Handler {
+	/// `ready` fires again on every reconnect, the global commands only need to be set once per run
+	slash_commands_registered: AtomicBool,
+}
 
 #[async_trait]
 impl EventHandler for Handler {
 	async fn channel_create(&self, context: serenity::client::Context, channel: &serenity::model::channel::GuildChannel) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("ChannelCreate", &channel.guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(channel.guild_id, db);
-			let event_info = events::EventType::ChannelCreate(events::ChannelCreateEventInfo::new(channel.id));
-			let environment = Environment::new(event_info, channel.guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("ChannelCreate", &context, output).await;
-		}
+		let event_info = events::EventType::ChannelCreate(events::ChannelCreateEventInfo::new(channel.id));
+		run_event("ChannelCreate", channel.guild_id, event_info, &context).await;
 	}
 
 	async fn channel_delete(&self, context: serenity::client::Context, channel: &serenity::model::channel::GuildChannel) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("ChannelDelete", &channel.guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(channel.guild_id, db);
-			let event_info = events::EventType::ChannelDelete(events::ChannelDeleteEventInfo::new(channel.id));
-			let environment = Environment::new(event_info, channel.guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("ChannelDelete", &context, output).await;
-		}
+		let event_info = events::EventType::ChannelDelete(events::ChannelDeleteEventInfo::new(channel.id));
+		run_event("ChannelDelete", channel.guild_id, event_info, &context).await;
 	}
 
-	async fn channel_update(&self, context: serenity::client::Context, _old: Option<serenity::model::channel::Channel>, channel: serenity::model::channel::Channel) {
+	async fn channel_update(&self, context: serenity::client::Context, old: Option<serenity::model::channel::Channel>, channel: serenity::model::channel::Channel) {
 		let channel = channel.guild().unwrap();
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("ChannelUpdate", &channel.guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(channel.guild_id, db);
-			let event_info = events::EventType::ChannelUpdate(events::ChannelUpdateEventInfo::new(channel.id));
-			let environment = Environment::new(event_info, channel.guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("ChannelUpdate", &context, output).await;
+		let changes;
+		match old.and_then(|old| old.guild()) {
+			Some(old) => {
+				changes = diffs::ChannelDiff::new(&old, &channel).to_fields();
+			}
+			None => {
+				changes = HashMap::new();
+			}
 		}
+		let event_info = events::EventType::ChannelUpdate(events::ChannelUpdateEventInfo::new(channel.id, changes));
+		run_event("ChannelUpdate", channel.guild_id, event_info, &context).await;
 	}
 	
 
 	async fn guild_member_addition(&self, context: serenity::client::Context, guild_id: serenity::model::id::GuildId, new_member: serenity::model::guild::Member) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("MemberJoin", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::MemberJoin(events::MemberJoinEventInfo::new(new_member.user.id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("MemberJoin", &context, output).await;
-		}
+		let event_info = events::EventType::MemberJoin(events::MemberJoinEventInfo::new(new_member.user.id));
+		run_event("MemberJoin", guild_id, event_info, &context).await;
 	}
 
 	async fn guild_member_removal(&self, context: serenity::client::Context, guild_id: serenity::model::id::GuildId, user: serenity::model::prelude::User, _member_data_if_available: Option<serenity::model::guild::Member>) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("MemberLeave", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::MemberLeave(events::MemberLeaveEventInfo::new(user.id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("MemberLeave", &context, output).await;
-		}
+		let event_info = events::EventType::MemberLeave(events::MemberLeaveEventInfo::new(user.id));
+		run_event("MemberLeave", guild_id, event_info, &context).await;
 	}
 
-	async fn guild_member_update(&self, context: serenity::client::Context, _old_if_available: Option<serenity::model::guild::Member>, member: serenity::model::guild::Member) {
+	async fn guild_member_update(&self, context: serenity::client::Context, old_if_available: Option<serenity::model::guild::Member>, member: serenity::model::guild::Member) {
 		let guild_id = member.guild_id;
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("MemberUpdate", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::MemberUpdate(events::MemberUpdateEventInfo::new(member.user.id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("MemberUpdate", &context, output).await;
+		let changes;
+		match old_if_available {
+			Some(old) => {
+				changes = diffs::MemberDiff::new(&old, &member).to_fields();
+			}
+			None => {
+				changes = HashMap::new();
+			}
 		}
+		let event_info = events::EventType::MemberUpdate(events::MemberUpdateEventInfo::new(member.user.id, changes));
+		run_event("MemberUpdate", guild_id, event_info, &context).await;
 	}
 
 	async fn guild_role_create(&self, context: serenity::client::Context, guild_id: serenity::model::id::GuildId, new: serenity::model::guild::Role) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("RoleCreate", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::RoleCreate(events::RoleCreateEventInfo::new(new.id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("RoleCreate", &context, output).await;
-		}
+		let event_info = events::EventType::RoleCreate(events::RoleCreateEventInfo::new(new.id));
+		run_event("RoleCreate", guild_id, event_info, &context).await;
 	}
 
 	async fn guild_role_delete(&self, context: serenity::client::Context, guild_id: serenity::model::id::GuildId, removed_role_id: serenity::model::id::RoleId, _removed_role_data_if_available: Option<serenity::model::guild::Role>) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("RoleDelete", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::RoleDelete(events::RoleDeleteEventInfo::new(removed_role_id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("RoleDelete", &context, output).await;
-		}
+		let event_info = events::EventType::RoleDelete(events::RoleDeleteEventInfo::new(removed_role_id));
+		run_event("RoleDelete", guild_id, event_info, &context).await;
 	}
 
-	async fn guild_role_update(&self, context: serenity::client::Context, guild_id: serenity::model::id::GuildId, _old_data_if_available: Option<serenity::model::guild::Role>, new: serenity::model::guild::Role) {
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("RoleUpdate", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::RoleUpdate(events::RoleUpdateEventInfo::new(new.id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("RoleUpdate", &context, output).await;
+	async fn guild_role_update(&self, context: serenity::client::Context, guild_id: serenity::model::id::GuildId, old_data_if_available: Option<serenity::model::guild::Role>, new: serenity::model::guild::Role) {
+		let changes;
+		match old_data_if_available {
+			Some(old) => {
+				changes = diffs::RoleDiff::new(&old, &new).to_fields();
+			}
+			None => {
+				changes = HashMap::new();
+			}
 		}
+		let event_info = events::EventType::RoleUpdate(events::RoleUpdateEventInfo::new(new.id, changes));
+		run_event("RoleUpdate", guild_id, event_info, &context).await;
 	}
 
-	async fn guild_update(&self, context: serenity::client::Context, _old_data_if_available: Option<serenity::model::guild::Guild>, new: serenity::model::guild::PartialGuild) {
+	async fn guild_update(&self, context: serenity::client::Context, old_data_if_available: Option<serenity::model::guild::Guild>, new: serenity::model::guild::PartialGuild) {
 		let guild_id = new.id;
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("GuildUpdate", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::GuildUpdate(events::GuildUpdateEventInfo::new());
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("GuildUpdate", &context, output).await;
+		let changes;
+		match old_data_if_available {
+			Some(old) => {
+				changes = diffs::GuildDiff::new(&old, &new).to_fields();
+			}
+			None => {
+				changes = HashMap::new();
+			}
 		}
+		let event_info = events::EventType::GuildUpdate(events::GuildUpdateEventInfo::new(changes));
+		run_event("GuildUpdate", guild_id, event_info, &context).await;
 	}
 
 	async fn reaction_add(&self, context: serenity::client::Context, reaction: serenity::model::channel::Reaction) {
 		let guild_id = reaction.guild_id.unwrap();
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("ReactionAdd", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::ReactionAdd(events::ReactionAddEventInfo::new(reaction.channel_id, reaction.message_id, reaction.user_id.unwrap(), reaction.emoji));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("ReactionAdd", &context, output).await;
-		}
+		let event_info = events::EventType::ReactionAdd(events::ReactionAddEventInfo::new(reaction.channel_id, reaction.message_id, reaction.user_id.unwrap(), reaction.emoji));
+		run_event("ReactionAdd", guild_id, event_info, &context).await;
 	}
 
 	async fn reaction_remove(&self, context: serenity::client::Context, reaction: serenity::model::channel::Reaction) {
 		let guild_id = reaction.guild_id.unwrap();
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("ReactionRemove", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::ReactionRemove(events::ReactionRemoveEventInfo::new(reaction.channel_id, reaction.message_id, reaction.user_id.unwrap(), reaction.emoji));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("ReactionRemove", &context, output).await;
-		}
-	}
-
-	async fn voice_state_update(&self, context: serenity::client::Context, guild_id: Option<serenity::model::id::GuildId>, _old: Option<serenity::model::prelude::VoiceState>, new: serenity::model::prelude::VoiceState) {
-		let guild_id = guild_id.unwrap();
-		let lock = context.data.read().await;
-		let db = lock.get::<Database>().unwrap();
-		if let Some(code) = get_event_code("VoiceUpdate", &guild_id.to_string(), db).await {
-			let db_manager = SqlDatabaseManager::new(guild_id, db);
-			let event_info = events::EventType::VoiceUpdate(events::VoiceUpdateEventInfo::new(new.channel_id.unwrap(), new.user_id));
-			let environment = Environment::new(event_info, guild_id, &context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let output = yttrium::interpret_string(code, keys, environment).await;
-			send_output("VoiceUpdate", &context, output).await;
-		}
-	}
-}
\ No newline at end of file
+		let event_info = events::EventType::ReactionRemove(events::ReactionRemoveEventInfo::new(reaction.channel_id, reaction.message_id, reaction.user_id.unwrap(), reaction.emoji));
+		run_event("ReactionRemove", guild_id, event_info, &context).await;
+	}
+
+	async fn interaction_create(&self, context: serenity::client::Context, interaction: Interaction) {
+		match interaction {
+			Interaction::MessageComponent(interaction) => {
+				run_component_interaction(&context, interaction).await;
+			}
+			Interaction::ApplicationCommand(interaction) => {
+				slash_commands::run(&context, interaction).await;
+			}
+			Interaction::Autocomplete(interaction) => {
+				slash_commands::autocomplete(&context, interaction).await;
+			}
+			Interaction::ModalSubmit(interaction) => {
+				run_modal_submit(&context, interaction).await;
+			}
+			_ => {}
+		}
+	}
+
+	async fn ready(&self, context: serenity::client::Context, _ready: serenity::model::gateway::Ready) {
+		//Without an application ID set on the client there is nothing to register the commands under
+		if context.data.read().await.get::<BotConfig>().unwrap().application_id.is_none() {
+			return;
+		}
+		if self.slash_commands_registered.swap(true, Ordering::SeqCst) {
+			return;
+		}
+		if let Err(error) = slash_commands::register(&context).await {
+			tracing::warn!("Could not register slash commands: `{}`", error);
+			//Tried again on the next reconnect
+			self.slash_commands_registered.store(false, Ordering::SeqCst);
+		}
+	}
+
+	async fn voice_state_update(&self, context: serenity::client::Context, guild_id_if_available: Option<serenity::model::id::GuildId>, old: Option<serenity::model::prelude::VoiceState>, new: serenity::model::prelude::VoiceState) {
+		//Voice states outside of guilds (DM calls) can't have events attached to them
+		let guild_id;
+		match guild_id_if_available.or(new.guild_id) {
+			Some(id) => {
+				guild_id = id;
+			}
+			None => {
+				return;
+			}
+		}
+		let old_channel = old.as_ref().and_then(|state| state.channel_id);
+		let new_channel = new.channel_id;
+		for event_name in voice_event_names(old.as_ref(), &new) {
+			let event_info = events::VoiceUpdateEventInfo::new(old_channel, new_channel, new.user_id);
+			let event_info = match event_name {
+				"VoiceJoin" => {
+					events::EventType::VoiceJoin(event_info)
+				}
+				"VoiceLeave" => {
+					events::EventType::VoiceLeave(event_info)
+				}
+				"VoiceMove" => {
+					events::EventType::VoiceMove(event_info)
+				}
+				"VoiceMute" => {
+					events::EventType::VoiceMute(event_info)
+				}
+				"VoiceDeafen" => {
+					events::EventType::VoiceDeafen(event_info)
+				}
+				"VoiceStream" => {
+					events::EventType::VoiceStream(event_info)
+				}
+				_ => {
+					events::EventType::VoiceUpdate(event_info)
+				}
+			};
+			run_event(event_name, guild_id, event_info, &context).await;
+		}
+	}
+}
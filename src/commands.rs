@@ -5,52 +5,44 @@ use serenity::{
 		CommandResult,
 		macros::command,
 	},
+	http::AttachmentType,
 	model::{
 		channel::Message,
 		id::{
 			RoleId,
 			ChannelId,
+			GuildId,
 		},
 	},
 };
-use yttrium_key_base::environment::{Environment, events};
+use yttrium_key_base::environment::events;
 use crate::types::*;
 use crate::match_engine;
 use crate::utilities;
 use crate::databases;
+use crate::custom_commands;
+use crate::storage::CustomSlashCommand;
+
+/// How many lines `db list` shows at once
+const DB_PAGE_SIZE: usize = 20;
 
 #[command]
 async fn execute(context: &Context, message: &Message, args: Args) -> CommandResult {
 	let data = context.data.read().await;
-	let keys = data.get::<KeyList>().unwrap();
-	//Placeholder manager
-	let pool = data.get::<Database>().unwrap();
-	let db_manager = databases::SqlDatabaseManager::new(message.guild_id.unwrap(), pool);
-	let environment = Environment::new(events::EventType::Default, message.guild_id.unwrap(), &context, db_manager);
-	let output = yttrium::interpret_string(String::from(args.rest()), keys, environment).await;
-	match output {
-		Ok(result) => {
-			utilities::send_result(&context, result).await;
-		}
-		Err(error) => {
-			message.channel_id.say(&context.http, format!("{:#?}", error)).await.unwrap();
-		}
-	}
+	let options = crate::output::SendOptions { file_threshold: data.get::<BotConfig>().unwrap().output_file_threshold, ..Default::default() };
+	drop(data);
+	let channel_id = message.channel_id;
+	utilities::run_script(context, String::from(args.rest()), events::EventType::Default, message.guild_id.unwrap(), Some(message.clone()), move |context, error| async move {
+		crate::output::send_text(&context, channel_id, &format!("{:#?}", error), None, &options).await.unwrap();
+	}).await;
 	return Ok(());
 }
 
-#[command]
-async fn add(context: &Context, message: &Message, mut args: Args) -> CommandResult {
-	let trigger = args.single_quoted::<String>().unwrap();
-	args.unquoted();
-	let code = String::from(args.rest());
-	if code.is_empty() {
-		message.channel_id.say(&context.http, "The trigger does not have a response").await.unwrap();
-		return Ok(());
-	}
-	let data = context.data.read().await;
-	let keys = data.get::<KeyList>().unwrap();
-	match yttrium::tree_creator::create_ars_tree(code.clone(), &keys) {
+/// Checks that code can be turned into a tree, returning the warnings worth showing along with the confirmation
+async fn check_code(context: &Context, code: &str) -> Result<Option<String>, &'static str> {
+	let lock = context.data.read().await;
+	let keys = lock.get::<KeyList>().unwrap();
+	match yttrium::tree_creator::create_ars_tree(String::from(code), keys) {
 		Ok(tree) => {
 			match tree.warnings {
 				Some(warnings) => {
@@ -62,252 +54,308 @@ async fn add(context: &Context, message: &Message, mut args: Args) -> CommandRes
 							}
 						}
 					}
-					message.channel_id.say(&context.http, format!("Trigger added, but it has the following errors:\n {}", output)).await.unwrap();
+					return Ok(Some(output));
 				}
 				None => {
-					message.channel_id.say(&context.http, "Trigger added").await.unwrap();
+					return Ok(None);
 				}
 			}
-			let guild_id = message.guild_id.unwrap().to_string();
-			let lock = context.data.read().await;
-			let db = lock.get::<Database>().unwrap();
-			sqlx::query!("REPLACE INTO triggers VALUES (?, ?, ?)", trigger, code, guild_id).execute(db).await.unwrap();
 		}
 		Err(error) => {
 			match error {
 				yttrium::errors_and_warns::Error::WrongAmountOfParameters => {
-					message.channel_id.say(&context.http, "One of your keys has invalid amount of parameters").await.unwrap();
+					return Err("One of your keys has invalid amount of parameters");
 				}
 				yttrium::errors_and_warns::Error::EmptyParameter => {
-					message.channel_id.say(&context.http, "One of your keys has an empty parameter").await.unwrap();
+					return Err("One of your keys has an empty parameter");
 				}
 				yttrium::errors_and_warns::Error::NonexistentKey => {
-					message.channel_id.say(&context.http, "One of your keys does not exist").await.unwrap();
+					return Err("One of your keys does not exist");
+				}
+				yttrium::errors_and_warns::Error::InterpretationError(_) => {
+					return Err("The code could not be checked");
 				}
-				yttrium::errors_and_warns::Error::InterpretationError(_) => {}
 			}
 		}
 	}
-	return Ok(());
 }
 
-#[command]
-async fn remove(context: &Context, message: &Message, args: Args) -> CommandResult {
-	let trigger = args.parse::<String>().unwrap();
-	let guild_id = message.guild_id.unwrap().to_string();
-	let query = sqlx::query!("DELETE FROM triggers WHERE trigger = ? AND guild_id = ?", trigger, guild_id);
+/// Adds a trigger, the prefix and slash versions of `add` both end up here
+pub async fn add_trigger(context: &Context, guild_id: GuildId, trigger: &str, code: &str) -> String {
+	if code.is_empty() {
+		return String::from("The trigger does not have a response");
+	}
+	let response;
+	match check_code(context, code).await {
+		Ok(Some(warnings)) => {
+			response = format!("Trigger added, but it has the following errors:\n {}", warnings);
+		}
+		Ok(None) => {
+			response = String::from("Trigger added");
+		}
+		Err(error) => {
+			return String::from(error);
+		}
+	}
 	let lock = context.data.read().await;
 	let db = lock.get::<Database>().unwrap();
-	match query.execute(db).await.unwrap().rows_affected() {
-		0 => {
-			message.channel_id.say(&context.http, "Trigger not found").await.unwrap();
+	if let Err(error) = db.set_trigger(&guild_id.to_string(), trigger, code).await {
+		tracing::error!("{}", error);
+		return String::from("Could not add the trigger");
+	}
+	return response;
+}
+
+pub async fn remove_trigger(context: &Context, guild_id: GuildId, trigger: &str) -> String {
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	match db.remove_trigger(&guild_id.to_string(), trigger).await {
+		Ok(true) => {
+			return String::from("Trigger deleted");
 		}
-		_ => {
-			message.channel_id.say(&context.http, "Trigger deleted").await.unwrap();
+		Ok(false) => {
+			return String::from("Trigger not found");
+		}
+		Err(error) => {
+			tracing::error!("{}", error);
+			return String::from("Could not remove the trigger");
 		}
 	}
-	return Ok(());
 }
 
-#[command]
-async fn show(context: &Context, message: &Message, mut args: Args) -> CommandResult {
-	args.quoted();
-	let trigger = args.parse::<String>().unwrap();
-	let guild_id = message.guild_id.unwrap().to_string();
-	let query = sqlx::query!("SELECT code FROM triggers WHERE trigger = ? AND guild_id = ?", trigger, guild_id);
+pub async fn show_trigger(context: &Context, guild_id: GuildId, trigger: &str) -> String {
 	let lock = context.data.read().await;
 	let db = lock.get::<Database>().unwrap();
-	match query.fetch_optional(db).await {
-		Ok(Some(result)) => {
-			let code = result.code;
-			let trigger_type = match_engine::MatchType::new(trigger);
+	match db.get_trigger(&guild_id.to_string(), trigger).await {
+		Ok(Some(code)) => {
+			let trigger_type = match_engine::MatchType::new(String::from(trigger));
 			match trigger_type {
 				match_engine::MatchType::Literal(_) => {
-					message.channel_id.say(&context.http, format!("Trigger type: Literal```\n{}\n```", code)).await.unwrap();
+					return format!("Trigger type: Literal```\n{}\n```", code);
 				}
 				match_engine::MatchType::StartingLiteral(_) => {
-					message.channel_id.say(&context.http, format!("Trigger type: Starting literal\n```\n{}\n```", code)).await.unwrap();
+					return format!("Trigger type: Starting literal\n```\n{}\n```", code);
 				}
 				match_engine::MatchType::Regex(_) => {
-					message.channel_id.say(&context.http, format!("Trigger type: Regex\n```\n{}\n```", code)).await.unwrap();
+					return format!("Trigger type: Regex\n```\n{}\n```", code);
 				}
 			}
 		}
 		Ok(None) => {
-			message.channel_id.say(&context.http, "Trigger not found").await.unwrap();
+			return String::from("Trigger not found");
 		}
 		Err(error) => {
-			eprintln!("{}", error);
+			tracing::error!("{}", error);
+			return String::from("Could not read the trigger");
 		}
 	}
-	return Ok(());
 }
 
-#[command]
-async fn event_add(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+/// Adds code to an event, `event` is checked and capitalized here
+pub async fn add_event(context: &Context, guild_id: GuildId, name: &str, code: &str) -> String {
 	let event;
-	match args.current() {
+	match utilities::proper_event_name(name) {
 		Some(ev) => {
-			match utilities::proper_event_name(ev) {
-				Some(ev) => {
-					event = String::from(ev);
-				}
-				None => {
-					message.channel_id.say(&context.http, "You need to provide a correct event type").await.unwrap();
-					return Ok(());
-				}
-			}
+			event = ev;
 		}
 		None => {
-			message.channel_id.say(&context.http, "You need to provide a correct event type").await.unwrap();
-			return Ok(());
+			return String::from("You need to provide a correct event type");
 		}
 	}
-	args.advance();
-	let code = String::from(args.rest());
 	if code.is_empty() {
-		message.channel_id.say(&context.http, "You need to provide a response to the event").await.unwrap();
-		return Ok(());
+		return String::from("You need to provide a response to the event");
 	}
-	let lock = context.data.read().await;
-	let keys = lock.get::<KeyList>().unwrap();
-	match yttrium::tree_creator::create_ars_tree(code.clone(), &keys) {
-		Ok(tree) => {
-			match tree.warnings {
-				Some(warnings) => {
-					let mut output = String::new();
-					for warning in warnings {
-						match warning {
-							yttrium::errors_and_warns::Warning::UnclosedKeys => {
-								output.push_str("There are unclosed keys");
-							}
-						}
-					}
-					message.channel_id.say(&context.http, format!("Event added, but it has the following errors:\n {}", output)).await.unwrap();
-				}
-				None => {
-					message.channel_id.say(&context.http, "Event added").await.unwrap();
-				}
-			}
-			let guild_id = message.guild_id.unwrap().to_string();
-			let lock = context.data.read().await;
-			let db = lock.get::<Database>().unwrap();
-			let query = sqlx::query!("REPLACE INTO events VALUES (?, ?, ?)", event, guild_id, code);
-			query.execute(db).await.unwrap();
+	let response;
+	match check_code(context, code).await {
+		Ok(Some(warnings)) => {
+			response = format!("Event added, but it has the following errors:\n {}", warnings);
+		}
+		Ok(None) => {
+			response = String::from("Event added");
 		}
 		Err(error) => {
-			match error {
-				yttrium::errors_and_warns::Error::WrongAmountOfParameters => {
-					message.channel_id.say(&context.http, "One of your keys has invalid amount of parameters").await.unwrap();
-				}
-				yttrium::errors_and_warns::Error::EmptyParameter => {
-					message.channel_id.say(&context.http, "One of your keys has an empty parameter").await.unwrap();
-				}
-				yttrium::errors_and_warns::Error::NonexistentKey => {
-					message.channel_id.say(&context.http, "One of your keys does not exist").await.unwrap();
-				}
-				yttrium::errors_and_warns::Error::InterpretationError(_) => {}
-			}
+			return String::from(error);
 		}
 	}
-	return Ok(());
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	if let Err(error) = db.set_event(&guild_id.to_string(), event, code).await {
+		tracing::error!("{}", error);
+		return String::from("Could not add the event");
+	}
+	return response;
 }
 
-#[command]
-async fn event_remove(context: &Context, message: &Message, args: Args) -> CommandResult {
+pub async fn remove_event(context: &Context, guild_id: GuildId, name: &str) -> String {
 	let event;
-	match args.current() {
+	match utilities::proper_event_name(name) {
 		Some(ev) => {
-			match utilities::proper_event_name(ev) {
-				Some(ev) => {
-					event = ev;
-				}
-				None => {
-					message.channel_id.say(&context.http, "You need to provide a correct event type").await.unwrap();
-					return Ok(());
-				}
-			}
+			event = ev;
 		}
 		None => {
-			message.channel_id.say(&context.http, "You need to provide a correct event type").await.unwrap();
-			return Ok(());
+			return String::from("You need to provide a correct event type");
 		}
 	}
-	let guild_id = message.guild_id.unwrap().to_string();
 	let lock = context.data.read().await;
 	let db = lock.get::<Database>().unwrap();
-	let query = sqlx::query!("DELETE FROM events WHERE event = ? AND guild_id = ?", event, guild_id);
-	match query.execute(db).await.unwrap().rows_affected() {
-		0 => {
-			message.channel_id.say(&context.http, "Event not found").await.unwrap();
+	match db.remove_event(&guild_id.to_string(), event).await {
+		Ok(true) => {
+			return String::from("Event deleted");
 		}
-		_ => {
-			message.channel_id.say(&context.http, "Event deleted").await.unwrap();
+		Ok(false) => {
+			return String::from("Event not found");
+		}
+		Err(error) => {
+			tracing::error!("{}", error);
+			return String::from("Could not remove the event");
 		}
 	}
-	return Ok(());
 }
 
-#[command]
-async fn event_show(context: &Context, message: &Message, args: Args) -> CommandResult {
+pub async fn show_event(context: &Context, guild_id: GuildId, name: &str) -> String {
 	let event;
-	match args.current() {
+	match utilities::proper_event_name(name) {
 		Some(ev) => {
-			match utilities::proper_event_name(ev) {
-				Some(ev) => {
-					event = ev;
+			event = ev;
+		}
+		None => {
+			return String::from("You need to provide a correct event type");
+		}
+	}
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	match db.get_event(&guild_id.to_string(), event).await {
+		Ok(Some(code)) => {
+			return format!("```\n{}\n```", code);
+		}
+		Ok(None) => {
+			return String::from("Event not found");
+		}
+		Err(error) => {
+			tracing::error!("{}", error);
+			return String::from("Could not read the event");
+		}
+	}
+}
+
+/// Changes the prefix, or shows the current one if there is no new prefix
+pub async fn set_prefix(context: &Context, guild_id: GuildId, new_prefix: Option<&str>) -> String {
+	let guild_id = guild_id.to_string();
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	match new_prefix {
+		Some(new_prefix) => {
+			match utilities::set_guild_prefix(&guild_id, new_prefix, db).await {
+				Ok(true) => {
+					return format!("Your prefix has been updated to `{}`", new_prefix);
 				}
-				None => {
-					message.channel_id.say(&context.http, "You need to provide a correct event type").await.unwrap();
-					return Ok(());
+				Ok(false) => {
+					return String::from("Could not update the prefix");
+				}
+				Err(error) => {
+					tracing::error!("{}", error);
+					return String::from("Could not update the prefix");
 				}
 			}
 		}
 		None => {
-			message.channel_id.say(&context.http, "You need to provide a correct event type").await.unwrap();
-			return Ok(());
+			let default_prefix = &lock.get::<BotConfig>().unwrap().default_prefix;
+			let old_prefix = utilities::get_guild_prefix(&guild_id, default_prefix, db).await;
+			return format!("Your current prefix is: `{}`", old_prefix);
 		}
 	}
-	let guild_id = message.guild_id.unwrap().to_string();
+}
+
+/// Sets the role allowed to manage the bot, [None] leaves it to the Manage Server permission
+pub async fn set_admin_role(context: &Context, guild_id: GuildId, role: Option<RoleId>) -> String {
 	let lock = context.data.read().await;
 	let db = lock.get::<Database>().unwrap();
-	let query = sqlx::query!("SELECT code FROM events WHERE event = ? AND guild_id = ?", event, guild_id);
-	match query.fetch_optional(db).await {
-		Ok(Some(result)) => {
-			let code = result.code;
-			message.channel_id.say(&context.http, format!("```\n{}\n```", code)).await.unwrap();
+	match utilities::set_guild_admin(&guild_id.to_string(), role.map(|role| role.to_string()), db).await {
+		Ok(true) => {
+			return String::from("Your admin role has been updated");
 		}
-		Ok(None) => {
-			message.channel_id.say(&context.http, "Event not found").await.unwrap();
+		Ok(false) => {
+			return String::from("The update has failed");
 		}
 		Err(error) => {
-			eprintln!("{}", error);
+			tracing::error!("{}", error);
+			return String::from("The update has failed");
 		}
 	}
-	return Ok(());
 }
 
-#[command]
-async fn prefix(context: &Context, message: &Message, args: Args) -> CommandResult {
-	match args.parse::<String>() {
-		Ok(new_prefix) => {
-			let guild_id = message.guild_id.unwrap().to_string();
-			let lock = context.data.read().await;
-			let db = lock.get::<Database>().unwrap();
-			if utilities::set_guild_prefix(&guild_id, &new_prefix, db).await {
-				message.channel_id.say(&context.http, format!("Your prefix has been updated to `{}`", new_prefix)).await.unwrap();
-			} else {
-				message.channel_id.say(&context.http, "Could not update the prefix").await.unwrap();
-			}
+/// Sets the channel script errors are sent to, [None] only logs them
+pub async fn set_error_channel(context: &Context, guild_id: GuildId, channel: Option<ChannelId>) -> String {
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	match utilities::set_guild_error_channel(&guild_id.to_string(), channel.map(|channel| channel.to_string()), db).await {
+		Ok(true) => {
+			return String::from("Your error channel has been updated");
 		}
-		Err(_) => {
-			let guild_id = message.guild_id.unwrap().to_string();
-			let lock = context.data.read().await;
-			let db = lock.get::<Database>().unwrap();
-			let old_prefix = utilities::get_guild_prefix(&guild_id, db).await;
-			message.channel_id.say(&context.http, format!("Your current prefix is: `{}`", old_prefix)).await.unwrap();
+		Ok(false) => {
+			return String::from("The update has failed");
+		}
+		Err(error) => {
+			tracing::error!("{}", error);
+			return String::from("The update has failed");
 		}
 	}
+}
+
+#[command]
+async fn add(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let trigger = args.single_quoted::<String>().unwrap();
+	args.unquoted();
+	let response = add_trigger(context, message.guild_id.unwrap(), &trigger, args.rest()).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn remove(context: &Context, message: &Message, args: Args) -> CommandResult {
+	let trigger = args.parse::<String>().unwrap();
+	let response = remove_trigger(context, message.guild_id.unwrap(), &trigger).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn show(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	args.quoted();
+	let trigger = args.parse::<String>().unwrap();
+	let response = show_trigger(context, message.guild_id.unwrap(), &trigger).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn event_add(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let event = String::from(args.current().unwrap_or_default());
+	args.advance();
+	let response = add_event(context, message.guild_id.unwrap(), &event, args.rest()).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn event_remove(context: &Context, message: &Message, args: Args) -> CommandResult {
+	let response = remove_event(context, message.guild_id.unwrap(), args.current().unwrap_or_default()).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn event_show(context: &Context, message: &Message, args: Args) -> CommandResult {
+	let response = show_event(context, message.guild_id.unwrap(), args.current().unwrap_or_default()).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn prefix(context: &Context, message: &Message, args: Args) -> CommandResult {
+	let new_prefix = args.parse::<String>().ok();
+	let response = set_prefix(context, message.guild_id.unwrap(), new_prefix.as_deref()).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
 	return Ok(());
 }
 
@@ -317,33 +365,19 @@ async fn admin(context: &Context, message: &Message, args: Args) -> CommandResul
 	if message.mention_roles.is_empty() {
 		match args.parse::<RoleId>() {
 			Ok(role) => {
-				new_role_id = Some(role.to_string());
+				new_role_id = Some(role);
 			}
 			Err(_) => {
 				let possible_role_name = args.rest();
 				let guild = message.guild(&context.cache).await.unwrap();
-				match guild.role_by_name(possible_role_name) {
-					Some(role) => {
-						new_role_id = Some(role.id.to_string());
-					}
-					None => {
-						new_role_id = None;
-					}
-				}
+				new_role_id = guild.role_by_name(possible_role_name).map(|role| role.id);
 			}
 		}
 	} else {
-		new_role_id = Some(message.mention_roles[0].to_string());
-	}
-	let lock = context.data.read().await;
-	let db = lock.get::<Database>().unwrap();
-	let result = utilities::set_guild_admin(&message.guild_id.unwrap().to_string(), new_role_id, db).await;
-	std::mem::drop(lock);
-	if result {
-		message.channel_id.say(&context.http, "Your admin role has been updated").await.unwrap();
-	} else {
-		message.channel_id.say(&context.http, "The update has failed").await.unwrap();
+		new_role_id = Some(message.mention_roles[0]);
 	}
+	let response = set_admin_role(context, message.guild_id.unwrap(), new_role_id).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
 	return Ok(());
 }
 
@@ -353,32 +387,648 @@ async fn error_channel(context: &Context, message: &Message, args: Args) -> Comm
 	if message.mention_channels.is_empty() {
 		match args.parse::<ChannelId>() {
 			Ok(channel) => {
-				new_channel_id = Some(channel.to_string());
+				new_channel_id = Some(channel);
 			}
 			Err(_) => {
-				let possible_role_name = args.rest();
+				let possible_channel_name = args.rest();
 				let guild = message.guild(&context.cache).await.unwrap();
-				match guild.channel_id_from_name(&context, possible_role_name).await {
-					Some(role) => {
-						new_channel_id = Some(role.to_string());
-					}
-					None => {
-						new_channel_id = None;
-					}
-				}
+				new_channel_id = guild.channel_id_from_name(&context, possible_channel_name).await;
 			}
 		}
 	} else {
-		new_channel_id = Some(message.mention_channels[0].id.to_string());
+		new_channel_id = Some(message.mention_channels[0].id);
 	}
+	let response = set_error_channel(context, message.guild_id.unwrap(), new_channel_id).await;
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command]
+async fn usage(context: &Context, message: &Message) -> CommandResult {
+	let guild_id = message.guild_id.unwrap();
 	let lock = context.data.read().await;
 	let db = lock.get::<Database>().unwrap();
-	let result = utilities::set_guild_error_channel(&message.guild_id.unwrap().to_string(), new_channel_id, db).await;
+	let limits = lock.get::<BotConfig>().unwrap().quotas.for_guild(guild_id);
+	let result = db.usage(&guild_id.to_string()).await;
+	drop(lock);
+	match result {
+		Ok(usage) => {
+			let text = format!("Keys: {}/{}\nStored data: {}/{} bytes\nDatabases: {}/{}\nLargest allowed value: {} bytes", usage.keys, limits.max_keys, usage.bytes, limits.max_total_bytes, usage.databases, limits.max_databases, limits.max_value_size);
+			message.channel_id.say(&context.http, text).await.unwrap();
+		}
+		Err(error) => {
+			tracing::error!("{}", error);
+			message.channel_id.say(&context.http, "Could not read the usage").await.unwrap();
+		}
+	}
+	return Ok(());
+}
+
+/// Works out which page of `total` lines to show, pages are numbered from 1 and out of range pages are clamped
+/// Returns the page and how many pages there are
+fn page_of(total: usize, page: usize) -> (usize, usize) {
+	let page_count = std::cmp::max(1, (total + DB_PAGE_SIZE - 1) / DB_PAGE_SIZE);
+	return (std::cmp::min(std::cmp::max(page, 1), page_count), page_count);
+}
+
+fn format_page(lines: &[String], page: usize, page_count: usize) -> String {
+	return format!("Page {}/{}\n```\n{}\n```", page, page_count, lines.join("\n"));
+}
+
+/// Sends a response that might not fit in a message, split or as a file like script output
+async fn send_long(context: &Context, message: &Message, text: &str) {
+	let file_threshold = context.data.read().await.get::<BotConfig>().unwrap().output_file_threshold;
+	let options = crate::output::SendOptions { file_threshold, ..Default::default() };
+	if let Err(error) = crate::output::send_text(context, message.channel_id, text, None, &options).await {
+		tracing::warn!("Could not send a response in {}: {}", message.channel_id, error);
+	}
+}
+
+/// Quotes a CSV field if it contains anything that would break the row
+fn csv_field(field: &str) -> String {
+	if field.contains(|character| character == ',' || character == '"' || character == '\n' || character == '\r') {
+		return format!("\"{}\"", field.replace('"', "\"\""));
+	}
+	return String::from(field);
+}
+
+#[command("list")]
+async fn db_list(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap().to_string();
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap().clone();
+	drop(lock);
+	let first_argument = args.single::<String>().ok();
+	//A number is a page of the database list, unless a database has that name
+	let name;
+	match first_argument {
+		Some(given) if given.parse::<usize>().is_err() => {
+			name = Some(given);
+		}
+		Some(given) => {
+			match db.database_exists(&guild_id, &given).await {
+				Ok(true) => {
+					name = Some(given);
+				}
+				Ok(false) => {
+					name = None;
+					args.rewind();
+				}
+				Err(error) => {
+					databases::storage_error(error);
+					message.channel_id.say(&context.http, "Could not list the databases").await.unwrap();
+					return Ok(());
+				}
+			}
+		}
+		None => {
+			name = None;
+		}
+	}
+	let requested_page = args.single::<usize>().unwrap_or(1);
+	let response;
+	match name {
+		Some(name) => {
+			//`db list <name> [page]` lists keys, only the shown page is read
+			let total;
+			match db.count_keys(&guild_id, &name).await {
+				Ok(count) => {
+					total = count as usize;
+				}
+				Err(error) => {
+					databases::storage_error(error);
+					message.channel_id.say(&context.http, "Could not list the keys").await.unwrap();
+					return Ok(());
+				}
+			}
+			if total == 0 {
+				message.channel_id.say(&context.http, format!("Database `{}` is empty", name)).await.unwrap();
+				return Ok(());
+			}
+			let (page, page_count) = page_of(total, requested_page);
+			match db.list_keys(&guild_id, &name, ((page - 1) * DB_PAGE_SIZE) as i64, DB_PAGE_SIZE as i64).await {
+				Ok(keys) => {
+					response = format_page(&keys, page, page_count);
+				}
+				Err(error) => {
+					databases::storage_error(error);
+					message.channel_id.say(&context.http, "Could not list the keys").await.unwrap();
+					return Ok(());
+				}
+			}
+		}
+		None => {
+			//`db list [page]` lists databases, which the quotas keep few enough to read at once
+			let list;
+			match db.list_databases(&guild_id).await {
+				Ok(found) => {
+					list = found;
+				}
+				Err(error) => {
+					databases::storage_error(error);
+					message.channel_id.say(&context.http, "Could not list the databases").await.unwrap();
+					return Ok(());
+				}
+			}
+			if list.is_empty() {
+				message.channel_id.say(&context.http, "This server has no databases").await.unwrap();
+				return Ok(());
+			}
+			let (page, page_count) = page_of(list.len(), requested_page);
+			let now = crate::storage::unix_now();
+			let lines = list.into_iter().skip((page - 1) * DB_PAGE_SIZE).take(DB_PAGE_SIZE).map(|info| {
+				let days = (now - info.created_at) / (24 * 60 * 60);
+				let mut line = format!("{} ({} keys, created {} days ago)", info.name, info.keys, days);
+				if let Some(description) = info.description {
+					line.push_str(&format!(" - {}", description));
+				}
+				return line;
+			}).collect::<Vec<String>>();
+			response = format_page(&lines, page, page_count);
+		}
+	}
+	send_long(context, message, &response).await;
+	return Ok(());
+}
+
+#[command("get")]
+async fn db_get(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap();
+	let name;
+	let key;
+	match (args.single::<String>(), args.single::<String>()) {
+		(Ok(given_name), Ok(given_key)) => {
+			name = given_name;
+			key = given_key;
+		}
+		_ => {
+			message.channel_id.say(&context.http, "Usage: `db get <database> <key>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let response;
+	match db.get_key(&guild_id.to_string(), &name, &key).await {
+		Ok(Some(stored)) => {
+			response = format!("Value of `{}` in `{}` ({}):\n```\n{}\n```", key, name, stored.value_type.as_str(), stored.text);
+		}
+		Ok(None) => {
+			response = format!("`{}` doesn't exist in `{}`", key, name);
+		}
+		Err(error) => {
+			response = format!("Could not read `{}`: {}", key, databases::storage_error(error));
+		}
+	}
+	send_long(context, message, &response).await;
+	return Ok(());
+}
+
+#[command("set")]
+async fn db_set(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap();
+	let name;
+	let key;
+	match (args.single::<String>(), args.single::<String>()) {
+		(Ok(given_name), Ok(given_key)) if !args.is_empty() => {
+			name = given_name;
+			key = given_key;
+		}
+		_ => {
+			message.channel_id.say(&context.http, "Usage: `db set <database> <key> <value>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	let value = databases::infer_stored(String::from(args.rest()));
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap().clone();
+	let limits = lock.get::<BotConfig>().unwrap().quotas.for_guild(guild_id);
 	drop(lock);
-	if result {
-		message.channel_id.say(&context.http, "Your error channel has been updated").await.unwrap();
+	let response;
+	match db.write_key(&guild_id.to_string(), &name, &key, &value, None, &limits).await {
+		Ok(()) => {
+			response = format!("`{}` in `{}` has been set", key, name);
+		}
+		Err(error) => {
+			response = format!("Could not set `{}`: {}", key, databases::quota_error(error));
+		}
+	}
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command("delete")]
+async fn db_delete(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap().to_string();
+	let name;
+	let key;
+	match (args.single::<String>(), args.single::<String>()) {
+		(Ok(given_name), Ok(given_key)) => {
+			name = given_name;
+			key = given_key;
+		}
+		_ => {
+			message.channel_id.say(&context.http, "Usage: `db delete <database> <key>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let response;
+	match db.key_exists(&guild_id, &name, &key).await {
+		Ok(true) => {
+			match db.remove_key(&guild_id, &name, &key).await {
+				Ok(()) => {
+					response = format!("`{}` has been removed from `{}`", key, name);
+				}
+				Err(error) => {
+					response = format!("Could not remove `{}`: {}", key, databases::storage_error(error));
+				}
+			}
+		}
+		Ok(false) => {
+			response = format!("`{}` doesn't exist in `{}`", key, name);
+		}
+		Err(error) => {
+			response = format!("Could not remove `{}`: {}", key, databases::storage_error(error));
+		}
+	}
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command("clear")]
+async fn db_clear(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap();
+	let name;
+	match args.single::<String>() {
+		Ok(given) => {
+			name = given;
+		}
+		Err(_) => {
+			message.channel_id.say(&context.http, "Usage: `db clear <database>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let response;
+	match db.clear_database(&guild_id.to_string(), &name).await {
+		Ok(()) => {
+			response = format!("`{}` has been cleared", name);
+		}
+		Err(error) => {
+			response = format!("Could not clear `{}`: {}", name, databases::storage_error(error));
+		}
+	}
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command("drop")]
+async fn db_drop(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap().to_string();
+	let name;
+	match args.single::<String>() {
+		Ok(given) => {
+			name = given;
+		}
+		Err(_) => {
+			message.channel_id.say(&context.http, "Usage: `db drop <database>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let response;
+	match db.database_exists(&guild_id, &name).await {
+		Ok(true) => {
+			match db.drop_database(&guild_id, &name).await {
+				Ok(()) => {
+					response = format!("`{}` has been dropped", name);
+				}
+				Err(error) => {
+					response = format!("Could not drop `{}`: {}", name, databases::storage_error(error));
+				}
+			}
+		}
+		Ok(false) => {
+			response = format!("`{}` doesn't exist", name);
+		}
+		Err(error) => {
+			response = format!("Could not drop `{}`: {}", name, databases::storage_error(error));
+		}
+	}
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command("describe")]
+async fn db_describe(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap();
+	let name;
+	match args.single::<String>() {
+		Ok(given) => {
+			name = given;
+		}
+		Err(_) => {
+			message.channel_id.say(&context.http, "Usage: `db describe <database> [description]`").await.unwrap();
+			return Ok(());
+		}
+	}
+	//No description removes the current one
+	let description = Some(args.rest()).filter(|description| !description.is_empty());
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let response;
+	match db.set_database_description(&guild_id.to_string(), &name, description).await {
+		Ok(true) => {
+			response = format!("The description of `{}` has been updated", name);
+		}
+		Ok(false) => {
+			response = format!("`{}` doesn't exist", name);
+		}
+		Err(error) => {
+			response = format!("Could not describe `{}`: {}", name, databases::storage_error(error));
+		}
+	}
+	message.channel_id.say(&context.http, response).await.unwrap();
+	return Ok(());
+}
+
+#[command("export")]
+async fn db_export(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap();
+	let name;
+	match args.single::<String>() {
+		Ok(given) => {
+			name = given;
+		}
+		Err(_) => {
+			message.channel_id.say(&context.http, "Usage: `db export <database> [json|csv]`").await.unwrap();
+			return Ok(());
+		}
+	}
+	let format = args.single::<String>().unwrap_or_else(|_| String::from("json")).to_lowercase();
+	if format != "json" && format != "csv" {
+		message.channel_id.say(&context.http, "The export format has to be `json` or `csv`").await.unwrap();
+		return Ok(());
+	}
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	match db.database_exists(&guild_id.to_string(), &name).await {
+		Ok(true) => {}
+		Ok(false) => {
+			message.channel_id.say(&context.http, format!("`{}` doesn't exist", name)).await.unwrap();
+			return Ok(());
+		}
+		Err(error) => {
+			message.channel_id.say(&context.http, format!("Could not export `{}`: {}", name, databases::storage_error(error))).await.unwrap();
+			return Ok(());
+		}
+	}
+	let pairs;
+	match db.dump_database(&guild_id.to_string(), &name).await {
+		Ok(dumped) => {
+			pairs = dumped;
+		}
+		Err(error) => {
+			message.channel_id.say(&context.http, format!("Could not export `{}`: {}", name, databases::storage_error(error))).await.unwrap();
+			return Ok(());
+		}
+	}
+	let contents;
+	if format == "csv" {
+		let mut csv = String::from("key,value\n");
+		for (key, value) in pairs {
+			csv.push_str(&format!("{},{}\n", csv_field(&key), csv_field(&value.text)));
+		}
+		contents = csv;
 	} else {
-		message.channel_id.say(&context.http, "The update has failed").await.unwrap();
+		let object = pairs.into_iter().map(|(key, value)| (key, value.to_json())).collect::<serde_json::Map<String, serde_json::Value>>();
+		contents = serde_json::to_string_pretty(&object).unwrap();
+	}
+	let size_limit = utilities::upload_size_limit(context, guild_id).await;
+	if contents.len() as u64 > size_limit {
+		message.channel_id.say(&context.http, format!("The export of `{}` is {} bytes long, the limit is {} bytes", name, contents.len(), size_limit)).await.unwrap();
+		return Ok(());
+	}
+	let file = AttachmentType::Bytes { data: contents.into_bytes().into(), filename: format!("{}.{}", name, format) };
+	let sent = message.channel_id.send_files(&context.http, vec![file], |new_message| {
+		return new_message.content(format!("Contents of `{}`", name));
+	}).await;
+	if let Err(error) = sent {
+		tracing::error!("Could not send the export of `{}` on guild `{}`: `{}`", name, guild_id, error);
+		message.channel_id.say(&context.http, format!("Could not send the export of `{}`", name)).await.unwrap();
 	}
 	return Ok(());
-}
\ No newline at end of file
+}
+
+/// Parses the `on`/`off` argument of settings commands
+fn parse_switch(text: &str) -> Option<bool> {
+	match text.to_lowercase().as_str() {
+		"on" | "yes" | "true" => {
+			return Some(true);
+		}
+		"off" | "no" | "false" => {
+			return Some(false);
+		}
+		_ => {
+			return None;
+		}
+	}
+}
+
+fn on_off(value: bool) -> &'static str {
+	if value {
+		return "on";
+	}
+	return "off";
+}
+
+#[command]
+async fn mentions(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap().to_string();
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let mut settings;
+	match db.get_output_settings(&guild_id).await {
+		Ok(found) => {
+			settings = found;
+		}
+		Err(error) => {
+			tracing::error!("mentions: DB error on guild `{}`: `{}`", guild_id, error);
+			message.channel_id.say(&context.http, "Could not read the settings").await.unwrap();
+			return Ok(());
+		}
+	}
+	if args.is_empty() {
+		let text = format!("Pings allowed in script output:\n`everyone`: {} (also covers `@here`)\n`roles`: {}\n`users`: {}\n`reply`: {} (pinging the author of the message replied to)", on_off(settings.allow_everyone), on_off(settings.allow_roles), on_off(settings.allow_users), on_off(settings.ping_on_reply));
+		message.channel_id.say(&context.http, text).await.unwrap();
+		return Ok(());
+	}
+	let kind = args.single::<String>().unwrap().to_lowercase();
+	let value;
+	match args.single::<String>().ok().as_deref().and_then(parse_switch) {
+		Some(given) => {
+			value = given;
+		}
+		None => {
+			message.channel_id.say(&context.http, "Usage: `mentions <everyone|roles|users|reply> <on|off>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	match kind.as_str() {
+		"everyone" => {
+			settings.allow_everyone = value;
+		}
+		"roles" => {
+			settings.allow_roles = value;
+		}
+		"users" => {
+			settings.allow_users = value;
+		}
+		"reply" => {
+			settings.ping_on_reply = value;
+		}
+		_ => {
+			message.channel_id.say(&context.http, "Usage: `mentions <everyone|roles|users|reply> <on|off>`").await.unwrap();
+			return Ok(());
+		}
+	}
+	match db.set_output_settings(&guild_id, &settings).await {
+		Ok(true) => {
+			message.channel_id.say(&context.http, format!("`{}` pings are now {}", kind, on_off(value))).await.unwrap();
+		}
+		Ok(false) => {
+			message.channel_id.say(&context.http, "The update has failed").await.unwrap();
+		}
+		Err(error) => {
+			tracing::error!("mentions: DB error on guild `{}`: `{}`", guild_id, error);
+			message.channel_id.say(&context.http, "The update has failed").await.unwrap();
+		}
+	}
+	return Ok(());
+}
+
+#[command]
+async fn reply(context: &Context, message: &Message, args: Args) -> CommandResult {
+	let guild_id = message.guild_id.unwrap().to_string();
+	let db = context.data.read().await.get::<Database>().unwrap().clone();
+	let mut settings;
+	match db.get_output_settings(&guild_id).await {
+		Ok(found) => {
+			settings = found;
+		}
+		Err(error) => {
+			tracing::error!("reply: DB error on guild `{}`: `{}`", guild_id, error);
+			message.channel_id.say(&context.http, "Could not read the settings").await.unwrap();
+			return Ok(());
+		}
+	}
+	if args.is_empty() {
+		message.channel_id.say(&context.http, format!("Replying to trigger messages is {}", on_off(settings.reply_to_trigger))).await.unwrap();
+		return Ok(());
+	}
+	match parse_switch(args.rest()) {
+		Some(value) => {
+			settings.reply_to_trigger = value;
+			match db.set_output_settings(&guild_id, &settings).await {
+				Ok(true) => {
+					message.channel_id.say(&context.http, format!("Replying to trigger messages is now {}", on_off(value))).await.unwrap();
+				}
+				Ok(false) => {
+					message.channel_id.say(&context.http, "The update has failed").await.unwrap();
+				}
+				Err(error) => {
+					tracing::error!("reply: DB error on guild `{}`: `{}`", guild_id, error);
+					message.channel_id.say(&context.http, "The update has failed").await.unwrap();
+				}
+			}
+		}
+		None => {
+			message.channel_id.say(&context.http, "Usage: `reply <on|off>`").await.unwrap();
+		}
+	}
+	return Ok(());
+}
+
+/// Usage: `slash_add <name> "<description>" [name:type[:description] ...] -- <code>`, optional options have a `?` after the type
+#[command]
+async fn slash_add(context: &Context, message: &Message, mut args: Args) -> CommandResult {
+	let name = args.single::<String>().unwrap_or_default().to_lowercase();
+	if let Err(error) = custom_commands::check_command_name(&name) {
+		message.channel_id.say(&context.http, error).await.unwrap();
+		return Ok(());
+	}
+	let description = args.single_quoted::<String>().unwrap_or_default();
+	if description.is_empty() || description.chars().count() > custom_commands::MAX_DESCRIPTION_LENGTH {
+		message.channel_id.say(&context.http, format!("The description needs to be between 1 and {} characters long", custom_commands::MAX_DESCRIPTION_LENGTH)).await.unwrap();
+		return Ok(());
+	}
+	args.unquoted();
+	let mut options = Vec::new();
+	let mut separated = false;
+	//Quotes let option descriptions have spaces
+	while let Ok(token) = args.single_quoted::<String>() {
+		if token == "--" {
+			separated = true;
+			break;
+		}
+		match custom_commands::parse_option(&token) {
+			Ok(option) => {
+				options.push(option);
+			}
+			Err(error) => {
+				message.channel_id.say(&context.http, error).await.unwrap();
+				return Ok(());
+			}
+		}
+	}
+	if !separated {
+		message.channel_id.say(&context.http, "Usage: `slash_add <name> \"<description>\" [name:type[:description] ...] -- <code>`").await.unwrap();
+		return Ok(());
+	}
+	if let Err(error) = custom_commands::check_options(&options) {
+		message.channel_id.say(&context.http, error).await.unwrap();
+		return Ok(());
+	}
+	let code = String::from(args.rest());
+	if code.is_empty() {
+		message.channel_id.say(&context.http, "The command does not have a response").await.unwrap();
+		return Ok(());
+	}
+	let response;
+	match check_code(context, &code).await {
+		Ok(Some(warnings)) => {
+			response = format!("Command `/{}` added, but it has the following errors:\n {}", name, warnings);
+		}
+		Ok(None) => {
+			response = format!("Command `/{}` added", name);
+		}
+		Err(error) => {
+			message.channel_id.say(&context.http, error).await.unwrap();
+			return Ok(());
+		}
+	}
+	let command = CustomSlashCommand { name, description, options: serde_json::to_string(&options).unwrap(), code };
+	match custom_commands::add(context, message.guild_id.unwrap(), command).await {
+		Ok(()) => {
+			message.channel_id.say(&context.http, response).await.unwrap();
+		}
+		Err(error) => {
+			message.channel_id.say(&context.http, format!("Could not add the command: {}", error)).await.unwrap();
+		}
+	}
+	return Ok(());
+}
+
+#[command]
+async fn slash_remove(context: &Context, message: &Message, args: Args) -> CommandResult {
+	let name = args.current().unwrap_or_default().trim_start_matches('/').to_lowercase();
+	match custom_commands::remove(context, message.guild_id.unwrap(), &name).await {
+		Ok(true) => {
+			message.channel_id.say(&context.http, "Command deleted").await.unwrap();
+		}
+		Ok(false) => {
+			message.channel_id.say(&context.http, "Command not found").await.unwrap();
+		}
+		Err(error) => {
+			message.channel_id.say(&context.http, format!("Could not remove the command: {}", error)).await.unwrap();
+		}
+	}
+	return Ok(());
+}
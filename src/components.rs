@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+use serenity::{
+	builder::CreateComponents,
+	model::{
+		channel::ReactionType,
+		interactions::{
+			InteractionResponseType,
+			message_component::{
+				ActionRowComponent,
+				ButtonStyle,
+				InputTextStyle,
+				MessageComponentInteraction,
+			},
+			modal::ModalSubmitInteraction,
+		},
+	},
+	prelude::Context,
+};
+use yttrium_key_base::environment::components::{
+	ActionRow,
+	Button,
+	Component,
+	Modal,
+	SelectMenu,
+	TextInput,
+};
+use crate::config::QuotaLimits;
+use crate::storage::{
+	Storage,
+	StoredModal,
+};
+
+/// Discord's limits on components in a single message
+const MAX_ACTION_ROWS: usize = 5;
+const MAX_BUTTONS_PER_ROW: usize = 5;
+const MAX_SELECT_OPTIONS: usize = 25;
+const MAX_CUSTOM_ID_LENGTH: usize = 100;
+/// Discord's limits on modals
+const MAX_MODAL_INPUTS: usize = 5;
+const MAX_MODAL_TITLE_LENGTH: usize = 45;
+const MAX_INPUT_LABEL_LENGTH: usize = 45;
+const MAX_INPUT_LENGTH: u64 = 4000;
+
+/// The handler code of every component that has one, with the custom ID it's stored under
+pub fn handlers(rows: &[ActionRow]) -> Vec<(String, String)> {
+	let mut handlers = Vec::new();
+	for row in rows {
+		for component in &row.components {
+			let custom_id;
+			let handler;
+			match component {
+				Component::Button(button) => {
+					custom_id = button.custom_id.as_deref();
+					handler = button.handler.as_deref();
+				}
+				Component::SelectMenu(menu) => {
+					custom_id = Some(menu.custom_id.as_str());
+					handler = menu.handler.as_deref();
+				}
+			}
+			if let (Some(custom_id), Some(handler)) = (custom_id, handler) {
+				handlers.push((String::from(custom_id), String::from(handler)));
+			}
+		}
+	}
+	return handlers;
+}
+
+/// Stores handlers from [handlers] so that they run when their component is used
+/// Only done once the message with the components has been sent, a failed send would leave handlers nothing can use
+pub async fn store_handlers(handlers: Vec<(String, String)>, guild_id: &str, storage: &Arc<dyn Storage>, limits: &QuotaLimits) -> Result<(), String> {
+	for (custom_id, handler) in handlers {
+		if let Err(error) = storage.set_component_handler(guild_id, &custom_id, &handler, limits).await {
+			return Err(format!("could not store the handler of `{}`: {}", custom_id, crate::databases::quota_error(error)));
+		}
+	}
+	return Ok(());
+}
+
+/// Checks the action rows a script created against Discord's limits and builds them
+pub fn build(rows: Vec<ActionRow>) -> Result<CreateComponents, String> {
+	if rows.len() > MAX_ACTION_ROWS {
+		return Err(format!("a message can have at most {} action rows, the output has {}", MAX_ACTION_ROWS, rows.len()));
+	}
+	for row in &rows {
+		check_row(row)?;
+	}
+	let mut components = CreateComponents::default();
+	for row in rows {
+		components.create_action_row(|action_row| {
+			for component in row.components {
+				match component {
+					Component::Button(button) => {
+						action_row.create_button(|builder| {
+							match &button.url {
+								Some(url) => {
+									builder.style(ButtonStyle::Link).url(url);
+								}
+								None => {
+									builder.style(button_style(&button.style)).custom_id(button.custom_id.as_deref().unwrap_or_default());
+								}
+							}
+							if let Some(emoji) = button.emoji.as_deref().and_then(|emoji| ReactionType::try_from(emoji).ok()) {
+								builder.emoji(emoji);
+							}
+							return builder.label(&button.label).disabled(button.disabled);
+						});
+					}
+					Component::SelectMenu(menu) => {
+						action_row.create_select_menu(|builder| {
+							builder.custom_id(&menu.custom_id).min_values(menu.min_values).max_values(menu.max_values);
+							if let Some(placeholder) = &menu.placeholder {
+								builder.placeholder(placeholder);
+							}
+							return builder.options(|options| {
+								for option in &menu.options {
+									options.create_option(|builder| {
+										if let Some(description) = &option.description {
+											builder.description(description);
+										}
+										return builder.label(&option.label).value(&option.value).default_selection(option.default);
+									});
+								}
+								return options;
+							});
+						});
+					}
+				}
+			}
+			return action_row;
+		});
+	}
+	return Ok(components);
+}
+
+fn check_row(row: &ActionRow) -> Result<(), String> {
+	if row.components.is_empty() {
+		return Err(String::from("action rows can't be empty"));
+	}
+	let menus = row.components.iter().filter(|component| matches!(component, Component::SelectMenu(_))).count();
+	if menus > 0 && row.components.len() > 1 {
+		return Err(String::from("a select menu has to be alone in its action row"));
+	}
+	if row.components.len() > MAX_BUTTONS_PER_ROW {
+		return Err(format!("an action row can have at most {} buttons", MAX_BUTTONS_PER_ROW));
+	}
+	for component in &row.components {
+		match component {
+			Component::Button(button) => {
+				check_button(button)?;
+			}
+			Component::SelectMenu(menu) => {
+				check_select_menu(menu)?;
+			}
+		}
+	}
+	return Ok(());
+}
+
+fn check_button(button: &Button) -> Result<(), String> {
+	match (&button.url, &button.custom_id) {
+		(Some(_), Some(_)) => {
+			return Err(format!("button `{}` can't have both a URL and a custom ID", button.label));
+		}
+		(None, None) => {
+			return Err(format!("button `{}` needs a URL or a custom ID", button.label));
+		}
+		(None, Some(custom_id)) => {
+			return check_custom_id(custom_id);
+		}
+		(Some(_), None) => {
+			if button.handler.is_some() {
+				return Err(format!("link button `{}` can't have a handler, Discord doesn't report clicks on links", button.label));
+			}
+			return Ok(());
+		}
+	}
+}
+
+fn check_select_menu(menu: &SelectMenu) -> Result<(), String> {
+	check_custom_id(&menu.custom_id)?;
+	if menu.options.is_empty() || menu.options.len() > MAX_SELECT_OPTIONS {
+		return Err(format!("select menu `{}` needs between 1 and {} options", menu.custom_id, MAX_SELECT_OPTIONS));
+	}
+	if menu.min_values > menu.max_values || menu.max_values as usize > menu.options.len() {
+		return Err(format!("select menu `{}` allows choosing between {} and {} of its {} options", menu.custom_id, menu.min_values, menu.max_values, menu.options.len()));
+	}
+	return Ok(());
+}
+
+fn check_custom_id(custom_id: &str) -> Result<(), String> {
+	if custom_id.is_empty() || custom_id.chars().count() > MAX_CUSTOM_ID_LENGTH {
+		return Err(format!("custom IDs have to be between 1 and {} characters long, `{}` isn't", MAX_CUSTOM_ID_LENGTH, custom_id));
+	}
+	return Ok(());
+}
+
+/// Unknown styles fall back to the grey secondary style
+fn button_style(style: &str) -> ButtonStyle {
+	match style.to_lowercase().as_str() {
+		"primary" | "blurple" => {
+			return ButtonStyle::Primary;
+		}
+		"success" | "green" => {
+			return ButtonStyle::Success;
+		}
+		"danger" | "red" => {
+			return ButtonStyle::Danger;
+		}
+		_ => {
+			return ButtonStyle::Secondary;
+		}
+	}
+}
+
+/// A text input of a stored modal, kept as JSON in the modals table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModalInput {
+	pub custom_id: String,
+	pub label: String,
+	/// Multi-line input instead of a single line
+	pub long: bool,
+	pub required: bool,
+	pub min_length: Option<u64>,
+	pub max_length: Option<u64>,
+	pub placeholder: Option<String>,
+	/// Text already filled in
+	pub value: Option<String>,
+}
+
+impl From<TextInput> for ModalInput {
+	fn from(input: TextInput) -> Self {
+		return Self {
+			custom_id: input.custom_id,
+			label: input.label,
+			long: input.long,
+			required: input.required,
+			min_length: input.min_length,
+			max_length: input.max_length,
+			placeholder: input.placeholder,
+			value: input.value,
+		};
+	}
+}
+
+/// Checks the modals a script defined and stores them, a modal opens when a button with the same custom ID is used
+pub async fn store_modals(modals: Vec<Modal>, guild_id: &str, storage: &Arc<dyn Storage>, limits: &QuotaLimits) -> Result<(), String> {
+	for modal in &modals {
+		check_modal(modal)?;
+	}
+	for modal in modals {
+		let inputs = modal.inputs.into_iter().map(ModalInput::from).collect::<Vec<ModalInput>>();
+		let stored = StoredModal { modal_id: modal.custom_id, title: modal.title, inputs: serde_json::to_string(&inputs).unwrap(), code: modal.handler };
+		if let Err(error) = storage.set_modal(guild_id, &stored, limits).await {
+			return Err(format!("could not store modal `{}`: {}", stored.modal_id, crate::databases::quota_error(error)));
+		}
+	}
+	return Ok(());
+}
+
+fn check_modal(modal: &Modal) -> Result<(), String> {
+	check_custom_id(&modal.custom_id)?;
+	if modal.title.is_empty() || modal.title.chars().count() > MAX_MODAL_TITLE_LENGTH {
+		return Err(format!("the title of modal `{}` needs to be between 1 and {} characters long", modal.custom_id, MAX_MODAL_TITLE_LENGTH));
+	}
+	if modal.inputs.is_empty() || modal.inputs.len() > MAX_MODAL_INPUTS {
+		return Err(format!("modal `{}` needs between 1 and {} text inputs", modal.custom_id, MAX_MODAL_INPUTS));
+	}
+	for (index, input) in modal.inputs.iter().enumerate() {
+		check_custom_id(&input.custom_id)?;
+		if modal.inputs[..index].iter().any(|other| other.custom_id == input.custom_id) {
+			return Err(format!("modal `{}` has more than one input called `{}`", modal.custom_id, input.custom_id));
+		}
+		if input.label.is_empty() || input.label.chars().count() > MAX_INPUT_LABEL_LENGTH {
+			return Err(format!("the label of input `{}` needs to be between 1 and {} characters long", input.custom_id, MAX_INPUT_LABEL_LENGTH));
+		}
+		let min_length = input.min_length.unwrap_or(0);
+		let max_length = input.max_length.unwrap_or(MAX_INPUT_LENGTH);
+		if min_length > max_length || max_length > MAX_INPUT_LENGTH {
+			return Err(format!("input `{}` allows between {} and {} characters, the limit is {}", input.custom_id, min_length, max_length, MAX_INPUT_LENGTH));
+		}
+	}
+	return Ok(());
+}
+
+/// Opens a stored modal as the response to a used component
+pub async fn open_modal(context: &Context, interaction: &MessageComponentInteraction, modal: &StoredModal) -> Result<(), String> {
+	let inputs: Vec<ModalInput> = serde_json::from_str(&modal.inputs).map_err(|error| format!("the inputs of modal `{}` are broken: {}", modal.modal_id, error))?;
+	let result = interaction.create_interaction_response(&context.http, |response| {
+		return response.kind(InteractionResponseType::Modal).interaction_response_data(|data| {
+			return data.custom_id(&modal.modal_id).title(&modal.title).components(|components| {
+				for input in &inputs {
+					components.create_action_row(|row| {
+						return row.create_input_text(|builder| {
+							let style;
+							if input.long {
+								style = InputTextStyle::Paragraph;
+							} else {
+								style = InputTextStyle::Short;
+							}
+							builder.custom_id(&input.custom_id).label(&input.label).style(style).required(input.required);
+							if let Some(min_length) = input.min_length {
+								builder.min_length(min_length);
+							}
+							if let Some(max_length) = input.max_length {
+								builder.max_length(max_length);
+							}
+							if let Some(placeholder) = &input.placeholder {
+								builder.placeholder(placeholder);
+							}
+							if let Some(value) = &input.value {
+								builder.value(value);
+							}
+							return builder;
+						});
+					});
+				}
+				return components;
+			});
+		});
+	}).await;
+	return result.map_err(|error| error.to_string());
+}
+
+/// The text entered in a submitted modal, by the custom ID of each input
+pub fn submitted_values(interaction: &ModalSubmitInteraction) -> HashMap<String, String> {
+	let mut values = HashMap::new();
+	for row in &interaction.data.components {
+		for component in &row.components {
+			if let ActionRowComponent::InputText(input) = component {
+				values.insert(input.custom_id.clone(), input.value.clone());
+			}
+		}
+	}
+	return values;
+}
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Deserialize;
+use serenity::client::bridge::gateway::GatewayIntents;
+
+/// Where the config file is looked for if `--config` wasn't passed
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+const USAGE: &str = "Usage: yttrium-bot [OPTIONS]
+
+Options:
+	--config <PATH>          Config file to read, defaults to `config.toml` if it exists
+	--token <TOKEN>          Discord bot token [env: DISCORD_TOKEN]
+	--database-url <URL>     Database to connect to, like `sqlite:data.db` or `postgres://localhost/bot` [env: DATABASE_URL]
+	--prefix <PREFIX>        Prefix used in guilds which didn't set their own [env: BOT_DEFAULT_PREFIX]
+	--intents <INTENTS>      Comma separated list of gateway intents, or `all` [env: BOT_INTENTS]
+	--log-level <LEVEL>      One of `error`, `warn`, `info`, `debug`, `trace` [env: BOT_LOG_LEVEL]
+	--migrate-only           Applies pending database migrations and exits
+	--help                   Prints this message";
+
+/// Runtime configuration of the bot
+/// The config file is read first, then environment variables and command line flags are applied on top of it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub token: String,
+	/// Needed to register slash commands, they are left out if it isn't set
+	pub application_id: Option<u64>,
+	pub database_url: String,
+	pub default_prefix: String,
+	pub intents: Vec<String>,
+	pub log_level: String,
+	/// How often expired script database keys are removed, in seconds
+	pub key_sweep_interval: u64,
+	/// Script output longer than this many bytes is sent as a file instead of being split into messages, 0 never sends a file
+	pub output_file_threshold: usize,
+	/// Largest file scripts can attach, in bytes, lowered to what Discord allows in the guild
+	pub max_attachment_size: u64,
+	/// How long the handlers of components and modals are kept after scripts store them, in seconds, 0 keeps them forever
+	pub handler_lifetime: u64,
+	/// Limits on how much each guild can store in script databases
+	pub quotas: Quotas,
+	/// Only set from the command line
+	#[serde(skip)]
+	pub migrate_only: bool,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		return Self {
+			token: String::new(),
+			application_id: None,
+			database_url: String::from("sqlite:data.db"),
+			default_prefix: String::from("."),
+			intents: vec![String::from("all")],
+			log_level: String::from("info"),
+			key_sweep_interval: 300,
+			output_file_threshold: 8000,
+			max_attachment_size: 8 * 1024 * 1024,
+			handler_lifetime: 30 * 24 * 60 * 60,
+			quotas: Quotas::default(),
+			migrate_only: false,
+		};
+	}
+}
+
+impl Config {
+	/// Builds the configuration from the config file, environment and command line, and validates it
+	pub fn load() -> Result<Self, ConfigError> {
+		let arguments = Arguments::parse(std::env::args().skip(1))?;
+		let mut config;
+		match &arguments.config_path {
+			Some(path) => {
+				config = Self::from_file(path)?;
+			}
+			None => {
+				if Path::new(DEFAULT_CONFIG_PATH).exists() {
+					config = Self::from_file(DEFAULT_CONFIG_PATH)?;
+				} else {
+					config = Self::default();
+				}
+			}
+		}
+		config.apply_environment();
+		config.apply_arguments(arguments);
+		config.validate()?;
+		return Ok(config);
+	}
+
+	pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+		match std::fs::read_to_string(path) {
+			Ok(content) => {
+				return toml::from_str(&content).map_err(|error| ConfigError::Parse(String::from(path), error));
+			}
+			Err(error) => {
+				return Err(ConfigError::Io(String::from(path), error));
+			}
+		}
+	}
+
+	fn apply_environment(&mut self) {
+		if let Ok(token) = std::env::var("DISCORD_TOKEN") {
+			self.token = token;
+		}
+		if let Ok(database_url) = std::env::var("DATABASE_URL") {
+			self.database_url = database_url;
+		}
+		if let Ok(prefix) = std::env::var("BOT_DEFAULT_PREFIX") {
+			self.default_prefix = prefix;
+		}
+		if let Ok(intents) = std::env::var("BOT_INTENTS") {
+			self.intents = split_list(&intents);
+		}
+		if let Ok(log_level) = std::env::var("BOT_LOG_LEVEL") {
+			self.log_level = log_level;
+		}
+	}
+
+	fn apply_arguments(&mut self, arguments: Arguments) {
+		if let Some(token) = arguments.token {
+			self.token = token;
+		}
+		if let Some(database_url) = arguments.database_url {
+			self.database_url = database_url;
+		}
+		if let Some(prefix) = arguments.default_prefix {
+			self.default_prefix = prefix;
+		}
+		if let Some(intents) = arguments.intents {
+			self.intents = split_list(&intents);
+		}
+		if let Some(log_level) = arguments.log_level {
+			self.log_level = log_level;
+		}
+		self.migrate_only = arguments.migrate_only;
+	}
+
+	/// Checks everything that can be checked before connecting anywhere
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		//Migrating doesn't log into Discord, so there's no need for a token
+		if self.token.trim().is_empty() && !self.migrate_only {
+			return Err(ConfigError::Invalid(String::from("no bot token was provided")));
+		}
+		if !crate::storage::is_supported_url(&self.database_url) {
+			return Err(ConfigError::Invalid(format!("`{}` is not a supported database URL, it needs to start with `sqlite:` or `postgres:`", self.database_url)));
+		}
+		if self.default_prefix.is_empty() || self.default_prefix.contains(char::is_whitespace) {
+			return Err(ConfigError::Invalid(String::from("the default prefix can't be empty or contain whitespace")));
+		}
+		for guild_id in self.quotas.guilds.keys() {
+			if guild_id.parse::<u64>().is_err() {
+				return Err(ConfigError::Invalid(format!("quota overrides need to be keyed by guild ID, `{}` isn't one", guild_id)));
+			}
+		}
+		if self.key_sweep_interval == 0 {
+			return Err(ConfigError::Invalid(String::from("the key sweep interval needs to be at least one second")));
+		}
+		self.gateway_intents()?;
+		self.tracing_level()?;
+		return Ok(());
+	}
+
+	pub fn gateway_intents(&self) -> Result<GatewayIntents, ConfigError> {
+		let mut intents = GatewayIntents::empty();
+		for name in &self.intents {
+			match intent_from_name(name) {
+				Some(intent) => {
+					intents |= intent;
+				}
+				None => {
+					return Err(ConfigError::Invalid(format!("`{}` is not a gateway intent", name)));
+				}
+			}
+		}
+		if intents.is_empty() {
+			return Err(ConfigError::Invalid(String::from("at least one gateway intent needs to be enabled")));
+		}
+		return Ok(intents);
+	}
+
+	pub fn tracing_level(&self) -> Result<tracing::Level, ConfigError> {
+		return self.log_level.parse::<tracing::Level>().map_err(|_| ConfigError::Invalid(format!("`{}` is not a log level", self.log_level)));
+	}
+}
+
+/// Script database limits, applied to every guild unless overridden in `guilds`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+	pub max_keys: u64,
+	pub max_total_bytes: u64,
+	pub max_value_size: u64,
+	pub max_databases: u64,
+	/// Component handlers and modals together
+	pub max_handlers: u64,
+	/// Overrides keyed by guild ID
+	pub guilds: HashMap<String, QuotaOverrides>,
+}
+
+impl Default for Quotas {
+	fn default() -> Self {
+		return Self {
+			max_keys: 10_000,
+			max_total_bytes: 5 * 1024 * 1024,
+			max_value_size: 64 * 1024,
+			max_databases: 50,
+			max_handlers: 1000,
+			guilds: HashMap::new(),
+		};
+	}
+}
+
+impl Quotas {
+	/// Returns the limits of the guild, taking its overrides into account
+	pub fn for_guild(&self, guild_id: serenity::model::id::GuildId) -> QuotaLimits {
+		let mut limits = QuotaLimits {
+			max_keys: self.max_keys,
+			max_total_bytes: self.max_total_bytes,
+			max_value_size: self.max_value_size,
+			max_databases: self.max_databases,
+			max_handlers: self.max_handlers,
+		};
+		if let Some(overrides) = self.guilds.get(&guild_id.to_string()) {
+			limits.max_keys = overrides.max_keys.unwrap_or(limits.max_keys);
+			limits.max_total_bytes = overrides.max_total_bytes.unwrap_or(limits.max_total_bytes);
+			limits.max_value_size = overrides.max_value_size.unwrap_or(limits.max_value_size);
+			limits.max_databases = overrides.max_databases.unwrap_or(limits.max_databases);
+			limits.max_handlers = overrides.max_handlers.unwrap_or(limits.max_handlers);
+		}
+		return limits;
+	}
+}
+
+/// Per guild overrides of [Quotas], [None] means the global value is used
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaOverrides {
+	pub max_keys: Option<u64>,
+	pub max_total_bytes: Option<u64>,
+	pub max_value_size: Option<u64>,
+	pub max_databases: Option<u64>,
+	pub max_handlers: Option<u64>,
+}
+
+/// The limits that apply to a single guild
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotaLimits {
+	pub max_keys: u64,
+	pub max_total_bytes: u64,
+	pub max_value_size: u64,
+	pub max_databases: u64,
+	pub max_handlers: u64,
+}
+
+/// Values passed on the command line, [None] if not passed
+#[derive(Debug, Default)]
+struct Arguments {
+	config_path: Option<String>,
+	token: Option<String>,
+	database_url: Option<String>,
+	default_prefix: Option<String>,
+	intents: Option<String>,
+	log_level: Option<String>,
+	migrate_only: bool,
+}
+
+impl Arguments {
+	fn parse<I: Iterator<Item = String>>(mut arguments: I) -> Result<Self, ConfigError> {
+		let mut result = Self::default();
+		while let Some(argument) = arguments.next() {
+			let slot = match argument.as_str() {
+				"--config" => {
+					&mut result.config_path
+				}
+				"--token" => {
+					&mut result.token
+				}
+				"--database-url" => {
+					&mut result.database_url
+				}
+				"--prefix" => {
+					&mut result.default_prefix
+				}
+				"--intents" => {
+					&mut result.intents
+				}
+				"--log-level" => {
+					&mut result.log_level
+				}
+				"--migrate-only" => {
+					result.migrate_only = true;
+					continue;
+				}
+				"--help" | "-h" => {
+					println!("{}", USAGE);
+					std::process::exit(0);
+				}
+				_ => {
+					return Err(ConfigError::Arguments(format!("unknown argument `{}`", argument)));
+				}
+			};
+			match arguments.next() {
+				Some(value) => {
+					*slot = Some(value);
+				}
+				None => {
+					return Err(ConfigError::Arguments(format!("`{}` requires a value", argument)));
+				}
+			}
+		}
+		return Ok(result);
+	}
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(String, std::io::Error),
+	Parse(String, toml::de::Error),
+	Arguments(String),
+	Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(path, error) => {
+				return write!(formatter, "could not read config file `{}`: {}", path, error);
+			}
+			Self::Parse(path, error) => {
+				return write!(formatter, "could not parse config file `{}`: {}", path, error);
+			}
+			Self::Arguments(error) => {
+				return write!(formatter, "{}\n\n{}", error, USAGE);
+			}
+			Self::Invalid(error) => {
+				return write!(formatter, "invalid configuration: {}", error);
+			}
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+fn split_list(list: &str) -> Vec<String> {
+	return list.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect();
+}
+
+fn intent_from_name(name: &str) -> Option<GatewayIntents> {
+	match name.to_ascii_lowercase().as_ref() {
+		"all" => {
+			return Some(GatewayIntents::all());
+		}
+		"non_privileged" => {
+			return Some(GatewayIntents::non_privileged());
+		}
+		"guilds" => {
+			return Some(GatewayIntents::GUILDS);
+		}
+		"guild_members" => {
+			return Some(GatewayIntents::GUILD_MEMBERS);
+		}
+		"guild_bans" => {
+			return Some(GatewayIntents::GUILD_BANS);
+		}
+		"guild_emojis" => {
+			return Some(GatewayIntents::GUILD_EMOJIS);
+		}
+		"guild_integrations" => {
+			return Some(GatewayIntents::GUILD_INTEGRATIONS);
+		}
+		"guild_webhooks" => {
+			return Some(GatewayIntents::GUILD_WEBHOOKS);
+		}
+		"guild_invites" => {
+			return Some(GatewayIntents::GUILD_INVITES);
+		}
+		"guild_voice_states" => {
+			return Some(GatewayIntents::GUILD_VOICE_STATES);
+		}
+		"guild_presences" => {
+			return Some(GatewayIntents::GUILD_PRESENCES);
+		}
+		"guild_messages" => {
+			return Some(GatewayIntents::GUILD_MESSAGES);
+		}
+		"guild_message_reactions" => {
+			return Some(GatewayIntents::GUILD_MESSAGE_REACTIONS);
+		}
+		"guild_message_typing" => {
+			return Some(GatewayIntents::GUILD_MESSAGE_TYPING);
+		}
+		"direct_messages" => {
+			return Some(GatewayIntents::DIRECT_MESSAGES);
+		}
+		"direct_message_reactions" => {
+			return Some(GatewayIntents::DIRECT_MESSAGE_REACTIONS);
+		}
+		"direct_message_typing" => {
+			return Some(GatewayIntents::DIRECT_MESSAGE_TYPING);
+		}
+		_ => {
+			return None;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(arguments: &[&str]) -> Result<Arguments, ConfigError> {
+		return Arguments::parse(arguments.iter().map(|argument| String::from(*argument)));
+	}
+
+	fn valid() -> Config {
+		return Config { token: String::from("token"), ..Config::default() };
+	}
+
+	fn invalid(config: Config) -> String {
+		match config.validate() {
+			Err(ConfigError::Invalid(reason)) => {
+				return reason;
+			}
+			other => {
+				panic!("expected the config to be invalid, got {:?}", other);
+			}
+		}
+	}
+
+	#[test]
+	fn arguments_are_parsed() {
+		let arguments = parse(&["--config", "bot.toml", "--token", "abc", "--database-url", "postgres://localhost/bot", "--prefix", "!", "--intents", "guilds,guild_messages", "--log-level", "debug", "--migrate-only"]).unwrap();
+		assert_eq!(arguments.config_path.as_deref(), Some("bot.toml"));
+		assert_eq!(arguments.token.as_deref(), Some("abc"));
+		assert_eq!(arguments.database_url.as_deref(), Some("postgres://localhost/bot"));
+		assert_eq!(arguments.default_prefix.as_deref(), Some("!"));
+		assert_eq!(arguments.intents.as_deref(), Some("guilds,guild_messages"));
+		assert_eq!(arguments.log_level.as_deref(), Some("debug"));
+		assert!(arguments.migrate_only);
+		let empty = parse(&[]).unwrap();
+		assert!(empty.token.is_none() && !empty.migrate_only);
+	}
+
+	#[test]
+	fn bad_arguments_are_refused() {
+		assert!(matches!(parse(&["--token"]), Err(ConfigError::Arguments(_))));
+		assert!(matches!(parse(&["--verbose"]), Err(ConfigError::Arguments(_))));
+		assert!(matches!(parse(&["token"]), Err(ConfigError::Arguments(_))));
+	}
+
+	#[test]
+	fn arguments_override_the_file() {
+		let mut config = valid();
+		config.apply_arguments(parse(&["--prefix", "?", "--intents", " guilds , ,guild_messages"]).unwrap());
+		assert_eq!(config.default_prefix, "?");
+		assert_eq!(config.intents, vec!["guilds", "guild_messages"]);
+		assert_eq!(config.token, "token");
+		assert_eq!(config.gateway_intents().unwrap(), GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES);
+	}
+
+	#[test]
+	fn defaults_are_valid_with_a_token() {
+		assert!(valid().validate().is_ok());
+		invalid(Config::default());
+		assert!(Config { migrate_only: true, ..Config::default() }.validate().is_ok());
+	}
+
+	#[test]
+	fn invalid_values_are_refused() {
+		invalid(Config { database_url: String::from("mysql://localhost/bot"), ..valid() });
+		invalid(Config { default_prefix: String::new(), ..valid() });
+		invalid(Config { default_prefix: String::from("! "), ..valid() });
+		invalid(Config { key_sweep_interval: 0, ..valid() });
+		invalid(Config { intents: vec![String::from("guild_typos")], ..valid() });
+		invalid(Config { intents: Vec::new(), ..valid() });
+		invalid(Config { log_level: String::from("loud"), ..valid() });
+		let mut quotas = Quotas::default();
+		quotas.guilds.insert(String::from("my server"), QuotaOverrides::default());
+		invalid(Config { quotas, ..valid() });
+	}
+
+	#[test]
+	fn the_example_config_is_valid() {
+		let config: Config = toml::from_str(include_str!("../config.example.toml")).unwrap();
+		assert!(Config { token: String::from("token"), ..config }.validate().is_ok());
+		assert!(toml::from_str::<Config>("unknown_setting = 1").is_err());
+	}
+
+	#[test]
+	fn guild_quotas_override_the_global_ones() {
+		let config: Config = toml::from_str("[quotas]\nmax_keys = 100\n[quotas.guilds.\"42\"]\nmax_keys = 5\n").unwrap();
+		let overridden = config.quotas.for_guild(serenity::model::id::GuildId(42));
+		assert_eq!(overridden.max_keys, 5);
+		assert_eq!(overridden.max_databases, Quotas::default().max_databases);
+		assert_eq!(config.quotas.for_guild(serenity::model::id::GuildId(7)).max_keys, 100);
+	}
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+use serenity::{
+	model::{
+		id::GuildId,
+		interactions::{
+			InteractionResponseType,
+			application_command::{
+				ApplicationCommandInteraction,
+				ApplicationCommandInteractionDataOptionValue,
+				ApplicationCommandOptionType,
+			},
+		},
+	},
+	prelude::Context,
+};
+use yttrium_key_base::environment::events;
+use crate::storage::{
+	CustomSlashCommand,
+	Storage,
+};
+use crate::types::{
+	BotConfig,
+	Database,
+};
+use crate::utilities;
+
+/// Discord's limits on the commands of a single guild
+const MAX_GUILD_COMMANDS: usize = 100;
+const MAX_OPTIONS: usize = 25;
+const MAX_NAME_LENGTH: usize = 32;
+pub const MAX_DESCRIPTION_LENGTH: usize = 100;
+
+/// An option of a guild's own slash command, written as `name:type` or `name:type?` when it's optional
+/// A description can follow as `name:type:description`, quoted if it has spaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashOption {
+	pub name: String,
+	pub kind: OptionKind,
+	pub required: bool,
+	/// Options stored before descriptions existed don't have one, Discord is shown the name instead
+	#[serde(default)]
+	pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionKind {
+	String,
+	Integer,
+	Number,
+	Bool,
+	User,
+	Channel,
+	Role,
+}
+
+impl OptionKind {
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name.to_lowercase().as_str() {
+			"string" | "text" => {
+				return Some(Self::String);
+			}
+			"integer" | "int" => {
+				return Some(Self::Integer);
+			}
+			"number" | "float" => {
+				return Some(Self::Number);
+			}
+			"bool" | "boolean" => {
+				return Some(Self::Bool);
+			}
+			"user" | "member" => {
+				return Some(Self::User);
+			}
+			"channel" => {
+				return Some(Self::Channel);
+			}
+			"role" => {
+				return Some(Self::Role);
+			}
+			_ => {
+				return None;
+			}
+		}
+	}
+
+	fn option_type(&self) -> ApplicationCommandOptionType {
+		match self {
+			Self::String => {
+				return ApplicationCommandOptionType::String;
+			}
+			Self::Integer => {
+				return ApplicationCommandOptionType::Integer;
+			}
+			Self::Number => {
+				return ApplicationCommandOptionType::Number;
+			}
+			Self::Bool => {
+				return ApplicationCommandOptionType::Boolean;
+			}
+			Self::User => {
+				return ApplicationCommandOptionType::User;
+			}
+			Self::Channel => {
+				return ApplicationCommandOptionType::Channel;
+			}
+			Self::Role => {
+				return ApplicationCommandOptionType::Role;
+			}
+		}
+	}
+}
+
+/// Checks a command or option name against Discord's rules: lowercase letters, digits, `-` and `_`, at most 32 of them
+fn check_name(name: &str) -> Result<(), String> {
+	let valid_characters = name.chars().all(|character| character.is_lowercase() || character.is_numeric() || character == '-' || character == '_');
+	if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH || !valid_characters {
+		return Err(format!("`{}` isn't a valid name, names can have up to {} lowercase letters, digits, `-` and `_`", name, MAX_NAME_LENGTH));
+	}
+	return Ok(());
+}
+
+/// Checks the name of a new command, which also can't take the name of one of the bot's own commands
+pub fn check_command_name(name: &str) -> Result<(), String> {
+	check_name(name)?;
+	if crate::slash_commands::BUILTIN_COMMANDS.contains(&name) {
+		return Err(format!("`/{}` is one of the bot's own commands", name));
+	}
+	return Ok(());
+}
+
+pub fn parse_option(spec: &str) -> Result<SlashOption, String> {
+	let mut parts = spec.splitn(3, ':');
+	let name = parts.next().unwrap_or_default().to_lowercase();
+	check_name(&name)?;
+	let required;
+	let kind_name;
+	match parts.next().map(|kind| (kind, kind.strip_suffix('?'))) {
+		Some((_, Some(rest))) => {
+			kind_name = Some(rest);
+			required = false;
+		}
+		Some((kind, None)) => {
+			kind_name = Some(kind);
+			required = true;
+		}
+		None => {
+			kind_name = None;
+			required = true;
+		}
+	}
+	let description = parts.next().map(str::trim).filter(|description| !description.is_empty()).map(String::from);
+	if let Some(description) = &description {
+		if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+			return Err(format!("the description of option `{}` can be at most {} characters long", name, MAX_DESCRIPTION_LENGTH));
+		}
+	}
+	match kind_name.and_then(OptionKind::from_name) {
+		Some(kind) => {
+			return Ok(SlashOption { name, kind, required, description });
+		}
+		None => {
+			return Err(format!("option `{}` needs a type: string, integer, number, bool, user, channel or role", name));
+		}
+	}
+}
+
+/// Discord wants at most 25 options with unique names, and every required option before the optional ones
+pub fn check_options(options: &[SlashOption]) -> Result<(), String> {
+	if options.len() > MAX_OPTIONS {
+		return Err(format!("a command can have at most {} options", MAX_OPTIONS));
+	}
+	for (index, option) in options.iter().enumerate() {
+		if options[..index].iter().any(|other| other.name == option.name) {
+			return Err(format!("option `{}` is there more than once", option.name));
+		}
+		if option.required && options[..index].iter().any(|other| !other.required) {
+			return Err(format!("required option `{}` has to come before the optional ones", option.name));
+		}
+	}
+	return Ok(());
+}
+
+/// Pushes the guild's commands with this one added to Discord, then stores it
+/// Discord refusing the command leaves the stored commands as they were
+pub async fn add(context: &Context, guild_id: GuildId, command: CustomSlashCommand) -> Result<(), String> {
+	let db = stored_commands_database(context).await?;
+	let mut commands = db.list_slash_commands(&guild_id.to_string()).await.map_err(|error| error.to_string())?;
+	match commands.iter_mut().find(|other| other.name == command.name) {
+		Some(existing) => {
+			*existing = command.clone();
+		}
+		None => {
+			if commands.len() >= MAX_GUILD_COMMANDS {
+				return Err(format!("a server can have at most {} commands", MAX_GUILD_COMMANDS));
+			}
+			commands.push(command.clone());
+		}
+	}
+	push(context, guild_id, &commands).await?;
+	if let Err(error) = db.set_slash_command(&guild_id.to_string(), &command).await {
+		return Err(restore(context, guild_id, error).await);
+	}
+	return Ok(());
+}
+
+/// Returns `false` if there was no such command
+pub async fn remove(context: &Context, guild_id: GuildId, name: &str) -> Result<bool, String> {
+	let db = stored_commands_database(context).await?;
+	let mut commands = db.list_slash_commands(&guild_id.to_string()).await.map_err(|error| error.to_string())?;
+	let count = commands.len();
+	commands.retain(|command| command.name != name);
+	if commands.len() == count {
+		return Ok(false);
+	}
+	push(context, guild_id, &commands).await?;
+	if let Err(error) = db.remove_slash_command(&guild_id.to_string(), name).await {
+		return Err(restore(context, guild_id, error).await);
+	}
+	return Ok(true);
+}
+
+/// The storage, if the bot can register commands at all
+async fn stored_commands_database(context: &Context) -> Result<Arc<dyn Storage>, String> {
+	let lock = context.data.read().await;
+	if lock.get::<BotConfig>().unwrap().application_id.is_none() {
+		return Err(String::from("the bot needs `application_id` set in its config to register commands"));
+	}
+	return Ok(lock.get::<Database>().unwrap().clone());
+}
+
+/// Puts the stored commands back on Discord after storing a change failed, so that both keep the same commands
+async fn restore(context: &Context, guild_id: GuildId, error: sqlx::Error) -> String {
+	tracing::error!("Could not store the slash commands of guild `{}`: `{}`", guild_id, error);
+	if let Err(sync_error) = sync(context, guild_id).await {
+		tracing::warn!("Could not restore the slash commands of guild `{}`: `{}`", guild_id, sync_error);
+	}
+	return String::from("the command couldn't be saved, try again later");
+}
+
+/// Replaces the guild's slash commands on Discord with the stored ones
+pub async fn sync(context: &Context, guild_id: GuildId) -> Result<(), String> {
+	let commands = context.data.read().await.get::<Database>().unwrap().list_slash_commands(&guild_id.to_string()).await.map_err(|error| error.to_string())?;
+	return push(context, guild_id, &commands).await;
+}
+
+/// Replaces the guild's slash commands on Discord with `commands`
+async fn push(context: &Context, guild_id: GuildId, commands: &[CustomSlashCommand]) -> Result<(), String> {
+	let mut parsed = Vec::new();
+	for command in commands {
+		let options: Vec<SlashOption> = serde_json::from_str(&command.options).map_err(|error| format!("the options of `/{}` are broken: {}", command.name, error))?;
+		parsed.push((command, options));
+	}
+	let result = guild_id.set_application_commands(&context.http, |builder| {
+		for (command, options) in &parsed {
+			builder.create_application_command(|created| {
+				created.name(&command.name).description(&command.description);
+				for option in options {
+					created.create_option(|builder| {
+						let description = option.description.as_deref().unwrap_or(&option.name);
+						return builder.name(&option.name).description(description).kind(option.kind.option_type()).required(option.required);
+					});
+				}
+				return created;
+			});
+		}
+		return builder;
+	}).await;
+	return result.map(|_| ()).map_err(|error| error.to_string());
+}
+
+/// Runs the code of a guild's own command, with the option values in the environment
+/// Scripts can take longer than the 3 seconds Discord waits for a response, so the response is deferred and removed once the output is sent
+pub async fn run(context: &Context, interaction: ApplicationCommandInteraction) {
+	let guild_id;
+	match interaction.guild_id {
+		Some(id) => {
+			guild_id = id;
+		}
+		None => {
+			return;
+		}
+	}
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	let command;
+	match db.get_slash_command(&guild_id.to_string(), &interaction.data.name).await {
+		Ok(Some(found)) => {
+			command = found;
+		}
+		Ok(None) => {
+			crate::slash_commands::respond(context, &interaction, "This command doesn't exist anymore").await;
+			return;
+		}
+		Err(error) => {
+			tracing::error!("run: DB error with command: `{}` on guild `{}`: `{}`", interaction.data.name, guild_id, error);
+			return;
+		}
+	}
+	let deferred = interaction.create_interaction_response(&context.http, |response| {
+		return response.kind(InteractionResponseType::DeferredChannelMessageWithSource);
+	}).await;
+	if let Err(error) = deferred {
+		tracing::warn!("Could not defer the response to `/{}`: `{}`", command.name, error);
+	}
+	let mut values = HashMap::new();
+	for option in &interaction.data.options {
+		if let Some(value) = &option.resolved {
+			values.insert(option.name.clone(), option_text(value));
+		}
+	}
+	drop(lock);
+	let event_info = events::EventType::SlashCommand(events::SlashCommandEventInfo::new(interaction.channel_id, interaction.user.id, command.name.clone(), values));
+	let name = command.name.clone();
+	utilities::run_script(context, command.code, event_info, guild_id, None, move |context, error| async move {
+		utilities::report_error(&context, guild_id, &format!("Error in `/{}`: `{:#?}`", name, error)).await;
+	}).await;
+	if let Err(error) = interaction.delete_original_interaction_response(&context.http).await {
+		tracing::warn!("Could not remove the deferred response to `/{}`: `{}`", command.name, error);
+	}
+}
+
+/// Option values as scripts see them, users, channels and roles are given by ID
+fn option_text(value: &ApplicationCommandInteractionDataOptionValue) -> String {
+	match value {
+		ApplicationCommandInteractionDataOptionValue::String(text) => {
+			return text.clone();
+		}
+		ApplicationCommandInteractionDataOptionValue::Integer(number) => {
+			return number.to_string();
+		}
+		ApplicationCommandInteractionDataOptionValue::Number(number) => {
+			return number.to_string();
+		}
+		ApplicationCommandInteractionDataOptionValue::Boolean(value) => {
+			return value.to_string();
+		}
+		ApplicationCommandInteractionDataOptionValue::User(user, _) => {
+			return user.id.to_string();
+		}
+		ApplicationCommandInteractionDataOptionValue::Channel(channel) => {
+			return channel.id.to_string();
+		}
+		ApplicationCommandInteractionDataOptionValue::Role(role) => {
+			return role.id.to_string();
+		}
+		_ => {
+			return String::new();
+		}
+	}
+}
@@ -1,103 +1,538 @@
+use std::sync::Arc;
+use crate::config::QuotaLimits;
+use crate::storage::{QuotaError, Storage, StorageResult, StoredValue, ValueType};
+use yttrium_key_base::databases::StringOrArray;
+
+/// Storage used by the synchronous database traits that keys call
+/// Scripts can't await, so their calls are sent to a runtime running on a thread of its own and the script waits for the answer
+/// That runtime has its own connection pool, a query never needs a worker of the bot's runtime to make progress
+/// Waiting blocks the thread the script runs on, so scripts are run with `run_blocking` and never on a worker of the runtime
+pub struct ScriptStorage {
+	runtime: tokio::runtime::Handle,
+	storage: Arc<dyn Storage>,
+}
+
+impl ScriptStorage {
+	/// Starts the storage thread and connects to the database from inside of it
+	/// The database has to be migrated already
+	pub fn start(database_url: &str) -> StorageResult<Self> {
+		let database_url = String::from(database_url);
+		let (sender, receiver) = std::sync::mpsc::channel();
+		std::thread::Builder::new().name(String::from("script-storage")).spawn(move || {
+			let runtime;
+			match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+				Ok(built) => {
+					runtime = built;
+				}
+				Err(error) => {
+					let _ = sender.send(Err(sqlx::Error::Io(error)));
+					return;
+				}
+			}
+			let handle = runtime.handle().clone();
+			//The runtime keeps running its tasks for as long as the bot runs, it's only dropped here if connecting failed
+			runtime.block_on(async move {
+				match crate::storage::connect(&database_url).await {
+					Ok(storage) => {
+						let _ = sender.send(Ok((handle, storage)));
+						std::future::pending::<()>().await;
+					}
+					Err(error) => {
+						let _ = sender.send(Err(error));
+					}
+				}
+			});
+		})?;
+		let (runtime, storage) = receiver.recv().unwrap_or(Err(sqlx::Error::WorkerCrashed))?;
+		return Ok(Self { runtime, storage });
+	}
+
+	/// Runs `call` on the storage thread and blocks until it's done
+	fn run<T, E, F, R>(&self, call: F) -> Result<T, E>
+	where
+		T: Send + 'static,
+		E: From<sqlx::Error> + Send + 'static,
+		F: FnOnce(Arc<dyn Storage>) -> R + Send + 'static,
+		R: std::future::Future<Output = Result<T, E>> + Send + 'static,
+	{
+		let storage = Arc::clone(&self.storage);
+		let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+		self.runtime.spawn(async move {
+			let _ = sender.send(call(storage).await);
+		});
+		//The sender is only dropped without sending if the storage thread is gone
+		return receiver.recv().unwrap_or_else(|_| Err(E::from(sqlx::Error::WorkerCrashed)));
+	}
+}
+
+/// Runs a script on a thread of tokio's blocking pool and waits for it without blocking the current task
+/// Database calls in scripts block until the storage thread answers, which on a runtime worker would stall every task queued on it
+pub async fn run_blocking<F, R>(script: F) -> Result<R::Output, tokio::task::JoinError>
+where
+	F: FnOnce() -> R + Send + 'static,
+	R: std::future::Future,
+	R::Output: Send + 'static,
+{
+	let runtime = tokio::runtime::Handle::current();
+	return tokio::task::spawn_blocking(move || runtime.block_on(script())).await;
+}
+
+/// Logs a failed storage call and turns it into the error the script sees
+pub fn storage_error(error: sqlx::Error) -> String {
+	tracing::error!("script database error: {}", error);
+	return String::from("the database couldn't be reached, try again later");
+}
+
+/// Refused writes are explained to the script, storage failures go through [storage_error]
+pub fn quota_error(error: QuotaError) -> String {
+	match error {
+		QuotaError::Storage(error) => {
+			return storage_error(error);
+		}
+		refused => {
+			return refused.to_string();
+		}
+	}
+}
+
+/// Converts a value coming from a script into what is stored in the database
+/// Values keep the type the script gave them, so a string holding a number stays a string
+pub fn to_stored(value: StringOrArray) -> StoredValue {
+	match value {
+		StringOrArray::String(text) => {
+			return StoredValue::new(ValueType::String, text);
+		}
+		StringOrArray::Integer(number) => {
+			return StoredValue::new(ValueType::Integer, number.to_string());
+		}
+		StringOrArray::Float(number) => {
+			return StoredValue::new(ValueType::Float, number.to_string());
+		}
+		StringOrArray::Bool(value) => {
+			return StoredValue::new(ValueType::Bool, value.to_string());
+		}
+		StringOrArray::Array(_) => {
+			return StoredValue::new(ValueType::Array, to_json(value).to_string());
+		}
+		StringOrArray::Map(_) => {
+			return StoredValue::new(ValueType::Map, to_json(value).to_string());
+		}
+	}
+}
+
+/// Guesses the type of text that has none, like values typed in `db set` or stored before values had types
+/// Only text written exactly like [to_stored] writes an integer or a float is taken as a number, the typed_values migration follows the same rules
+pub fn infer_stored(text: String) -> StoredValue {
+	if text.parse::<i64>().map(|number| number.to_string() == text).unwrap_or(false) {
+		return StoredValue::new(ValueType::Integer, text);
+	}
+	//Whole floats like `-0` or `1e20` are written without a point, as text they are too ambiguous to be taken as floats
+	//The databases print floats with at most 15 digits and switch to exponents below 0.0001, the migration can only recognize what they print the same way
+	let digits = text.replace(&['-', '.'][..], "");
+	let in_range = |number: f64| number.is_finite() && number.abs() >= 0.0001 && digits.trim_start_matches('0').len() <= 15;
+	if text.contains('.') && text.parse::<f64>().map(|number| in_range(number) && number.to_string() == text).unwrap_or(false) {
+		return StoredValue::new(ValueType::Float, text);
+	}
+	return StoredValue::new(ValueType::String, text);
+}
+
+/// Converts a stored value back into what scripts use
+/// Values that don't match their type are returned as strings instead of being dropped
+fn from_stored(value: StoredValue) -> StringOrArray {
+	match value.value_type {
+		ValueType::String => {
+			return StringOrArray::String(value.text);
+		}
+		ValueType::Integer => {
+			return value.text.parse().map(StringOrArray::Integer).unwrap_or(StringOrArray::String(value.text));
+		}
+		ValueType::Float => {
+			return value.text.parse().map(StringOrArray::Float).unwrap_or(StringOrArray::String(value.text));
+		}
+		ValueType::Bool => {
+			return StringOrArray::Bool(value.text == "true");
+		}
+		ValueType::Array | ValueType::Map => {
+			match serde_json::from_str(&value.text) {
+				Ok(json) => {
+					return from_json(json);
+				}
+				Err(_) => {
+					return StringOrArray::String(value.text);
+				}
+			}
+		}
+	}
+}
+
+fn to_json(value: StringOrArray) -> serde_json::Value {
+	match value {
+		StringOrArray::String(text) => {
+			return serde_json::Value::String(text);
+		}
+		StringOrArray::Integer(number) => {
+			return serde_json::Value::from(number);
+		}
+		StringOrArray::Float(number) => {
+			//NaN and infinity can't be represented in JSON
+			return serde_json::Number::from_f64(number).map(serde_json::Value::Number).unwrap_or(serde_json::Value::Null);
+		}
+		StringOrArray::Bool(value) => {
+			return serde_json::Value::Bool(value);
+		}
+		StringOrArray::Array(values) => {
+			return serde_json::Value::Array(values.into_iter().map(to_json).collect());
+		}
+		StringOrArray::Map(values) => {
+			return serde_json::Value::Object(values.into_iter().map(|(key, value)| (key, to_json(value))).collect());
+		}
+	}
+}
+
+fn from_json(json: serde_json::Value) -> StringOrArray {
+	match json {
+		serde_json::Value::Null => {
+			return StringOrArray::String(String::new());
+		}
+		serde_json::Value::Bool(value) => {
+			return StringOrArray::Bool(value);
+		}
+		serde_json::Value::Number(number) => {
+			match number.as_i64() {
+				Some(integer) => {
+					return StringOrArray::Integer(integer);
+				}
+				None => {
+					return StringOrArray::Float(number.as_f64().unwrap_or(0.0));
+				}
+			}
+		}
+		serde_json::Value::String(text) => {
+			return StringOrArray::String(text);
+		}
+		serde_json::Value::Array(values) => {
+			return StringOrArray::Array(values.into_iter().map(from_json).collect());
+		}
+		serde_json::Value::Object(values) => {
+			return StringOrArray::Map(values.into_iter().map(|(key, value)| (key, from_json(value))).collect());
+		}
+	}
+}
+
 pub struct SqlDatabase {
 	guild_id: serenity::model::id::GuildId,
 	name: String,
-	pool: sqlx::SqlitePool,
+	storage: Arc<ScriptStorage>,
+	limits: QuotaLimits,
 }
 
 impl SqlDatabase {
-	pub fn new(guild_id: serenity::model::id::GuildId, pool: sqlx::SqlitePool, name: String) -> Self {
-		return Self { guild_id, pool, name };
+	pub fn new(guild_id: serenity::model::id::GuildId, storage: Arc<ScriptStorage>, name: String, limits: QuotaLimits) -> Self {
+		return Self { guild_id, storage, name, limits };
+	}
+
+	/// Owned copies of the guild, database and key names, the calls sent to the storage thread can't borrow them
+	fn names(&self, key: &str) -> (String, String, String) {
+		return (self.guild_id.to_string(), self.name.clone(), String::from(key));
 	}
 }
 
 pub struct SqlDatabaseManager {
 	guild_id: serenity::model::id::GuildId,
-	pool: sqlx::SqlitePool,
+	storage: Arc<ScriptStorage>,
+	limits: QuotaLimits,
 }
 
 impl SqlDatabaseManager {
-	pub fn new(guild_id: serenity::model::id::GuildId, pool: &sqlx::SqlitePool) -> Self {
+	pub fn new(guild_id: serenity::model::id::GuildId, storage: &Arc<ScriptStorage>, limits: QuotaLimits) -> Self {
 		return Self {
 			guild_id: guild_id,
-			pool: pool.clone(),
+			storage: Arc::clone(storage),
+			limits: limits,
 		};
 	}
 }
 
 impl yttrium_key_base::databases::Database for SqlDatabase {
-    fn get_key(&self, name: &str) -> Option<yttrium_key_base::databases::StringOrArray> {
-		let guild_id = self.guild_id.to_string();
-		let query = sqlx::query!("SELECT key_value FROM databases WHERE name = ? AND guild_id = ? AND key_name = ?", self.name, guild_id, name);
-		let result = futures::executor::block_on(query.fetch_one(&self.pool));
+    fn get_key(&self, name: &str) -> Option<StringOrArray> {
+		let (guild_id, database, name) = self.names(name);
+		let result = self.storage.run(move |storage| async move { storage.get_key(&guild_id, &database, &name).await });
 		match result {
-			Ok(result) => {
-				let content = result.key_value;
-				return Some(yttrium_key_base::databases::StringOrArray::String(content));
+			Ok(Some(content)) => {
+				return Some(from_stored(content));
+			}
+			Ok(None) => {
+				return None;
 			}
 			Err(error) => {
-				eprintln!("{}", error);
+				tracing::error!("{}", error);
 				return None;
 			}
 		}
     }
 
-    fn write_key(&mut self, name: String, value: yttrium_key_base::databases::StringOrArray) {
-		let to_insert;
-		match value {
-			yttrium_key_base::databases::StringOrArray::String(text) => {
-				to_insert = text;
-			}
-			yttrium_key_base::databases::StringOrArray::Array(_array) => {
-				todo!();
-			}
-		}
-		let guild_id = self.guild_id.to_string();
-		let query = sqlx::query!("REPLACE INTO databases VALUES (?, ?, ?, ?)", self.name, guild_id, name, to_insert);
-		futures::executor::block_on(query.execute(&self.pool)).unwrap();
+    fn write_key(&mut self, name: String, value: StringOrArray) -> Result<(), String> {
+		let to_insert = to_stored(value);
+		let (guild_id, database, name) = self.names(&name);
+		let limits = self.limits;
+		return self.storage.run(move |storage| async move { storage.write_key(&guild_id, &database, &name, &to_insert, None, &limits).await }).map_err(quota_error);
     }
 
-    fn remove_key(&mut self, name: &str) {
-		let guild_id = self.guild_id.to_string();
-        let query = sqlx::query!("DELETE FROM databases WHERE name = ? AND guild_id = ? AND key_name = ?", self.name, guild_id, name);
-		futures::executor::block_on(query.execute(&self.pool)).unwrap();
+	fn write_key_with_ttl(&mut self, name: String, value: StringOrArray, ttl: std::time::Duration) -> Result<(), String> {
+		let to_insert = to_stored(value);
+		let (guild_id, database, name) = self.names(&name);
+		let limits = self.limits;
+		let expires_at = crate::storage::unix_now() + ttl.as_secs() as i64;
+		return self.storage.run(move |storage| async move { storage.write_key(&guild_id, &database, &name, &to_insert, Some(expires_at), &limits).await }).map_err(quota_error);
+	}
+
+	fn set_key_ttl(&mut self, name: &str, ttl: Option<std::time::Duration>) -> Result<bool, String> {
+		let (guild_id, database, name) = self.names(name);
+		let expires_at = ttl.map(|ttl| crate::storage::unix_now() + ttl.as_secs() as i64);
+		return self.storage.run(move |storage| async move { storage.set_key_expiry(&guild_id, &database, &name, expires_at).await }).map_err(storage_error);
+	}
+
+    fn remove_key(&mut self, name: &str) -> Result<(), String> {
+		let (guild_id, database, name) = self.names(name);
+		return self.storage.run(move |storage| async move { storage.remove_key(&guild_id, &database, &name).await }).map_err(storage_error);
     }
 
     fn key_exists(&self, name: &str) -> bool {
-		let guild_id = self.guild_id.to_string();
-		let query = sqlx::query!("SELECT name FROM databases WHERE name = ? AND guild_id = ? AND key_name = ?", self.name, guild_id, name);
-		let result = futures::executor::block_on(query.fetch_optional(&self.pool));
+		let (guild_id, database, name) = self.names(name);
+		let result = self.storage.run(move |storage| async move { storage.key_exists(&guild_id, &database, &name).await });
 		match result {
-			Ok(Some(_)) => {
-				return true;
-			}
-			Ok(None) => {
-				return false;
+			Ok(exists) => {
+				return exists;
 			}
 			Err(error) => {
-				eprintln!("{}", error);
+				tracing::error!("{}", error);
 				return false;
 			}
 		}
     }
+
+	fn increment_key(&mut self, name: &str, amount: i64) -> Result<i64, String> {
+		let (guild_id, database, key) = self.names(name);
+		let limits = self.limits;
+		match self.storage.run(move |storage| async move { storage.increment_key(&guild_id, &database, &key, amount, &limits).await }).map_err(quota_error)? {
+			Some(value) => {
+				return Ok(value);
+			}
+			None => {
+				return Err(format!("`{}` doesn't hold an integer", name));
+			}
+		}
+	}
+
+	fn compare_and_swap(&mut self, name: &str, expected: Option<StringOrArray>, new: StringOrArray) -> Result<bool, String> {
+		let (guild_id, database, name) = self.names(name);
+		let limits = self.limits;
+		let expected = expected.map(to_stored);
+		let new = to_stored(new);
+		return self.storage.run(move |storage| async move { storage.compare_and_swap(&guild_id, &database, &name, expected.as_ref(), &new, &limits).await }).map_err(quota_error);
+	}
+
+	fn list_keys(&self) -> Vec<String> {
+		let (guild_id, database, _) = self.names("");
+		match self.storage.run(move |storage| async move { storage.list_keys(&guild_id, &database, 0, i64::MAX).await }) {
+			Ok(keys) => {
+				return keys;
+			}
+			Err(error) => {
+				tracing::error!("{}", error);
+				return Vec::new();
+			}
+		}
+	}
+
+	fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+		let (guild_id, database, prefix) = self.names(prefix);
+		match self.storage.run(move |storage| async move { storage.keys_with_prefix(&guild_id, &database, &prefix).await }) {
+			Ok(keys) => {
+				return keys;
+			}
+			Err(error) => {
+				tracing::error!("{}", error);
+				return Vec::new();
+			}
+		}
+	}
+
+	fn key_count(&self) -> usize {
+		let (guild_id, database, _) = self.names("");
+		match self.storage.run(move |storage| async move { storage.count_keys(&guild_id, &database).await }) {
+			Ok(count) => {
+				return count as usize;
+			}
+			Err(error) => {
+				tracing::error!("{}", error);
+				return 0;
+			}
+		}
+	}
+
+	fn top_keys(&self, amount: usize, descending: bool) -> Vec<(String, StringOrArray)> {
+		let (guild_id, database, _) = self.names("");
+		match self.storage.run(move |storage| async move { storage.top_keys(&guild_id, &database, amount as i64, descending).await }) {
+			Ok(keys) => {
+				return keys.into_iter().map(|(key, value)| (key, from_stored(value))).collect();
+			}
+			Err(error) => {
+				tracing::error!("{}", error);
+				return Vec::new();
+			}
+		}
+	}
 }
 
 impl yttrium_key_base::databases::DatabaseManager<SqlDatabase> for SqlDatabaseManager {
 	fn get_database(&mut self, name: &str) -> SqlDatabase {
-		return SqlDatabase::new(self.guild_id, self.pool.clone(), String::from(name))
+		return SqlDatabase::new(self.guild_id, Arc::clone(&self.storage), String::from(name), self.limits)
 	}
 
-	fn remove_database(&mut self, name: &str) {
+	/// Removes the database along with its keys, it's registered again on the next write
+	fn remove_database(&mut self, name: &str) -> Result<(), String> {
 		let guild_id = self.guild_id.to_string();
-		let query = sqlx::query!("DELETE FROM databases WHERE name = ? AND guild_id = ?", name, guild_id);
-		futures::executor::block_on(query.execute(&self.pool)).unwrap();
+		let name = String::from(name);
+		return self.storage.run(move |storage| async move { storage.drop_database(&guild_id, &name).await }).map_err(storage_error);
 	}
 
-	fn clear_database(&mut self, name: &str) {
+	/// Removes the keys, but keeps the database and its description
+	fn clear_database(&mut self, name: &str) -> Result<(), String> {
 		let guild_id = self.guild_id.to_string();
-		let query = sqlx::query!("DELETE FROM databases WHERE name = ? AND guild_id = ?", name, guild_id);
-		futures::executor::block_on(query.execute(&self.pool)).unwrap();
+		let name = String::from(name);
+		return self.storage.run(move |storage| async move { storage.clear_database(&guild_id, &name).await }).map_err(storage_error);
 	}
 }
 
-unsafe impl Send for SqlDatabaseManager {}
-unsafe impl Sync for SqlDatabaseManager {}
\ No newline at end of file
+//Environments holding these are created on whichever blocking thread runs the script, this fails to compile if either of them stops being thread safe
+const _: fn() = || {
+	fn assert_send_sync<T: Send + Sync>() {}
+	assert_send_sync::<SqlDatabaseManager>();
+	assert_send_sync::<SqlDatabase>();
+};
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Mutex;
+	use std::time::{Duration, Instant};
+	use serenity::model::id::GuildId;
+	use yttrium_key_base::databases::{
+		Database as _,
+		DatabaseManager as _,
+	};
+	use super::*;
+
+	const LIMITS: QuotaLimits = QuotaLimits {
+		max_keys: 10_000,
+		max_total_bytes: 1_000_000,
+		max_value_size: 1_000,
+		max_databases: 10,
+		max_handlers: 10,
+	};
+
+	/// Script storage has a pool of its own, so the tests use a file both pools can see
+	async fn start(name: &str) -> Arc<ScriptStorage> {
+		let path = std::env::temp_dir().join(format!("yttrium-bot-{}-{}.db", name, std::process::id()));
+		let _ = std::fs::remove_file(&path);
+		let database_url = format!("sqlite:{}", path.display());
+		crate::storage::connect(&database_url).await.unwrap().migrate().await.unwrap();
+		return Arc::new(ScriptStorage::start(&database_url).unwrap());
+	}
+
+	#[tokio::test]
+	async fn works_on_a_current_thread_runtime() {
+		let storage = start("current-thread").await;
+		let mut database = SqlDatabaseManager::new(GuildId(1), &storage, LIMITS).get_database("test");
+		database.write_key(String::from("key"), StringOrArray::String(String::from("value"))).unwrap();
+		assert!(database.key_exists("key"));
+		assert_eq!(database.increment_key("counter", 2), Ok(2));
+		assert!(database.increment_key("key", 1).is_err());
+	}
+
+	/// More scripts than workers, all of them waiting on queries at once, the runtime has to keep running other tasks meanwhile
+	#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+	async fn scripts_on_every_worker_dont_starve_queries() {
+		const SCRIPTS: usize = 16;
+		const INCREMENTS: usize = 50;
+		const TICK: Duration = Duration::from_millis(5);
+		//A few ticks of leeway for a loaded machine, a blocked worker stalls for as long as the scripts run
+		const MAX_GAP: Duration = Duration::from_millis(100);
+		let storage = start("starvation").await;
+		let heartbeats = Arc::new(Mutex::new(Vec::new()));
+		let heartbeat = {
+			let heartbeats = Arc::clone(&heartbeats);
+			tokio::spawn(async move {
+				loop {
+					heartbeats.lock().unwrap().push(Instant::now());
+					tokio::time::sleep(TICK).await;
+				}
+			})
+		};
+		let mut scripts = Vec::new();
+		for _ in 0..SCRIPTS {
+			let storage = Arc::clone(&storage);
+			scripts.push(tokio::spawn(run_blocking(move || async move {
+				let mut database = SqlDatabaseManager::new(GuildId(1), &storage, LIMITS).get_database("test");
+				for _ in 0..INCREMENTS {
+					database.increment_key("counter", 1).unwrap();
+				}
+			})));
+		}
+		let started = Instant::now();
+		let finished = tokio::time::timeout(Duration::from_secs(60), async move {
+			for script in scripts {
+				script.await.unwrap().unwrap();
+			}
+		}).await;
+		let ended = Instant::now();
+		heartbeat.abort();
+		assert!(finished.is_ok(), "scripts didn't finish, the queries they wait on are starved");
+		let database = SqlDatabaseManager::new(GuildId(1), &storage, LIMITS).get_database("test");
+		assert!(matches!(database.get_key("counter"), Some(StringOrArray::Integer(count)) if count == (SCRIPTS * INCREMENTS) as i64));
+		let mut beats: Vec<Instant> = heartbeats.lock().unwrap().iter().copied().filter(|beat| *beat >= started && *beat <= ended).collect();
+		beats.insert(0, started);
+		beats.push(ended);
+		let max_gap = beats.windows(2).map(|pair| pair[1] - pair[0]).max().unwrap();
+		assert!(max_gap < MAX_GAP, "the runtime stalled for {:?} while scripts were waiting on queries", max_gap);
+	}
+
+	#[test]
+	fn strings_stay_strings() {
+		for text in &["42", "1.5", "true", "hello"] {
+			let stored = to_stored(StringOrArray::String(String::from(*text)));
+			assert_eq!(stored.value_type, ValueType::String);
+			assert_eq!(stored.text, *text);
+		}
+		assert_eq!(to_stored(StringOrArray::Integer(42)).value_type, ValueType::Integer);
+		assert_eq!(to_stored(StringOrArray::Float(1.5)).value_type, ValueType::Float);
+	}
+
+	/// The same cases the typed_values migration is meant to classify, it has to stay in line with this
+	#[test]
+	fn untyped_text_is_a_number_only_in_canonical_form() {
+		let cases = [
+			("42", ValueType::Integer),
+			("-7", ValueType::Integer),
+			("9223372036854775807", ValueType::Integer),
+			("9223372036854775808", ValueType::String),
+			("-0", ValueType::String),
+			("007", ValueType::String),
+			("1.5", ValueType::Float),
+			("-3.25", ValueType::Float),
+			("1.50", ValueType::String),
+			("1.0", ValueType::String),
+			("1e5", ValueType::String),
+			("100000000000000000000", ValueType::String),
+			("0.0001", ValueType::Float),
+			("0.00001", ValueType::String),
+			("123456789012.345", ValueType::Float),
+			("0.30000000000000004", ValueType::String),
+			("true", ValueType::String),
+			("", ValueType::String),
+		];
+		for (text, value_type) in &cases {
+			assert_eq!(infer_stored(String::from(*text)).value_type, *value_type, "{}", text);
+		}
+	}
+}
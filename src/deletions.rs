@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serenity::{
+	http::Http,
+	model::{
+		channel::Message,
+		id::{
+			ChannelId,
+			MessageId,
+		},
+	},
+};
+use tokio::sync::Notify;
+use crate::storage::{self, ScheduledDeletion, Storage, StorageResult};
+
+/// Longest time the task waits before checking the queue again
+const MAX_WAIT: Duration = Duration::from_secs(60);
+/// Shortest time the task waits, so that a database which keeps failing isn't queried in a loop
+const MIN_WAIT: Duration = Duration::from_secs(1);
+
+/// Queues the message to be deleted after `delay` and wakes up the deletion task
+pub async fn schedule(storage: &Arc<dyn Storage>, wake_up: &Notify, message: &Message, delay: Duration) -> StorageResult<()> {
+	//Rounded up, so that messages are never deleted early
+	let delay = ((delay.as_millis() + 999) / 1000) as i64;
+	storage.schedule_deletion(&message.channel_id.to_string(), &message.id.to_string(), storage::unix_now() + delay).await?;
+	wake_up.notify_one();
+	return Ok(());
+}
+
+/// Deletes queued messages once they are due, runs for as long as the bot does
+/// The queue is stored in the database, so deletions queued before a restart still happen after it
+pub async fn run(http: Arc<Http>, storage: Arc<dyn Storage>, wake_up: Arc<Notify>) {
+	loop {
+		match storage.due_deletions(storage::unix_now()).await {
+			Ok(due) => {
+				for deletion in due {
+					delete(&http, &deletion).await;
+					if let Err(error) = storage.remove_deletion(deletion.id).await {
+						tracing::error!("deletions: DB error: `{}`", error);
+					}
+				}
+			}
+			Err(error) => {
+				tracing::error!("deletions: DB error: `{}`", error);
+			}
+		}
+		let mut wait = MAX_WAIT;
+		match storage.next_deletion_time().await {
+			Ok(Some(next)) => {
+				let until_next = Duration::from_secs(std::cmp::max(next - storage::unix_now(), 0) as u64);
+				wait = std::cmp::max(std::cmp::min(wait, until_next), MIN_WAIT);
+			}
+			Ok(None) => {}
+			Err(error) => {
+				tracing::error!("deletions: DB error: `{}`", error);
+			}
+		}
+		tokio::select! {
+			_ = tokio::time::sleep(wait) => {}
+			_ = wake_up.notified() => {}
+		}
+	}
+}
+
+/// Deletes the message, failures are only logged since the message may have been deleted by someone else already
+async fn delete(http: &Http, deletion: &ScheduledDeletion) {
+	match (deletion.channel_id.parse::<u64>(), deletion.message_id.parse::<u64>()) {
+		(Ok(channel_id), Ok(message_id)) => {
+			if let Err(error) = ChannelId(channel_id).delete_message(http, MessageId(message_id)).await {
+				tracing::warn!("deletions: could not delete message {} in {}: `{}`", message_id, channel_id, error);
+			}
+		}
+		_ => {
+			tracing::warn!("deletions: invalid IDs in the queue: `{}` `{}`", deletion.channel_id, deletion.message_id);
+		}
+	}
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use serenity::model::{
+	channel::GuildChannel,
+	guild::{
+		Guild,
+		Member,
+		PartialGuild,
+		Role,
+	},
+	id::{
+		ChannelId,
+		RoleId,
+		UserId,
+	},
+	Permissions,
+};
+
+/// A single value that is different between the old and the new version
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+	pub old: T,
+	pub new: T,
+}
+
+impl<T: PartialEq> Change<T> {
+	/// Returns [None] if nothing has changed
+	pub fn between(old: T, new: T) -> Option<Self> {
+		if old == new {
+			return None;
+		} else {
+			return Some(Self { old, new });
+		}
+	}
+}
+
+/// What changed on a member in `guild_member_update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberDiff {
+	pub nickname: Option<Change<Option<String>>>,
+	pub roles_added: Vec<RoleId>,
+	pub roles_removed: Vec<RoleId>,
+}
+
+impl MemberDiff {
+	pub fn new(old: &Member, new: &Member) -> Self {
+		return Self {
+			nickname: Change::between(old.nick.clone(), new.nick.clone()),
+			roles_added: new.roles.iter().filter(|role| !old.roles.contains(role)).copied().collect(),
+			roles_removed: old.roles.iter().filter(|role| !new.roles.contains(role)).copied().collect(),
+		};
+	}
+
+	pub fn to_fields(&self) -> HashMap<String, String> {
+		let mut fields = HashMap::new();
+		insert_change(&mut fields, "nickname", &self.nickname, |nick| nick.clone().unwrap_or_default());
+		insert_list(&mut fields, "roles", &self.roles_added, &self.roles_removed);
+		return fields;
+	}
+}
+
+/// What changed on a role in `guild_role_update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleDiff {
+	pub name: Option<Change<String>>,
+	pub permissions_added: Permissions,
+	pub permissions_removed: Permissions,
+	pub position: Option<Change<i64>>,
+	pub colour: Option<Change<u32>>,
+	pub hoist: Option<Change<bool>>,
+	pub mentionable: Option<Change<bool>>,
+}
+
+impl RoleDiff {
+	pub fn new(old: &Role, new: &Role) -> Self {
+		return Self {
+			name: Change::between(old.name.clone(), new.name.clone()),
+			permissions_added: new.permissions - old.permissions,
+			permissions_removed: old.permissions - new.permissions,
+			position: Change::between(old.position, new.position),
+			colour: Change::between(old.colour.0, new.colour.0),
+			hoist: Change::between(old.hoist, new.hoist),
+			mentionable: Change::between(old.mentionable, new.mentionable),
+		};
+	}
+
+	pub fn to_fields(&self) -> HashMap<String, String> {
+		let mut fields = HashMap::new();
+		insert_change(&mut fields, "name", &self.name, String::clone);
+		insert_permissions(&mut fields, self.permissions_added, self.permissions_removed);
+		insert_change(&mut fields, "position", &self.position, i64::to_string);
+		insert_change(&mut fields, "colour", &self.colour, |colour| format!("{:06x}", colour));
+		insert_change(&mut fields, "hoist", &self.hoist, bool::to_string);
+		insert_change(&mut fields, "mentionable", &self.mentionable, bool::to_string);
+		return fields;
+	}
+}
+
+/// What changed on a channel in `channel_update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelDiff {
+	pub name: Option<Change<String>>,
+	pub topic: Option<Change<Option<String>>>,
+	pub position: Option<Change<i64>>,
+	pub nsfw: Option<Change<bool>>,
+	pub category: Option<Change<Option<ChannelId>>>,
+}
+
+impl ChannelDiff {
+	pub fn new(old: &GuildChannel, new: &GuildChannel) -> Self {
+		return Self {
+			name: Change::between(old.name.clone(), new.name.clone()),
+			topic: Change::between(old.topic.clone(), new.topic.clone()),
+			position: Change::between(old.position, new.position),
+			nsfw: Change::between(old.nsfw, new.nsfw),
+			category: Change::between(old.category_id, new.category_id),
+		};
+	}
+
+	pub fn to_fields(&self) -> HashMap<String, String> {
+		let mut fields = HashMap::new();
+		insert_change(&mut fields, "name", &self.name, String::clone);
+		insert_change(&mut fields, "topic", &self.topic, |topic| topic.clone().unwrap_or_default());
+		insert_change(&mut fields, "position", &self.position, i64::to_string);
+		insert_change(&mut fields, "nsfw", &self.nsfw, bool::to_string);
+		insert_change(&mut fields, "category", &self.category, |category| category.map(|id| id.to_string()).unwrap_or_default());
+		return fields;
+	}
+}
+
+/// What changed on a guild in `guild_update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuildDiff {
+	pub name: Option<Change<String>>,
+	pub owner: Option<Change<UserId>>,
+	pub icon: Option<Change<Option<String>>>,
+	pub afk_timeout: Option<Change<u64>>,
+}
+
+impl GuildDiff {
+	pub fn new(old: &Guild, new: &PartialGuild) -> Self {
+		return Self {
+			name: Change::between(old.name.clone(), new.name.clone()),
+			owner: Change::between(old.owner_id, new.owner_id),
+			icon: Change::between(old.icon.clone(), new.icon.clone()),
+			afk_timeout: Change::between(old.afk_timeout, new.afk_timeout),
+		};
+	}
+
+	pub fn to_fields(&self) -> HashMap<String, String> {
+		let mut fields = HashMap::new();
+		insert_change(&mut fields, "name", &self.name, String::clone);
+		insert_change(&mut fields, "owner", &self.owner, UserId::to_string);
+		insert_change(&mut fields, "icon", &self.icon, |icon| icon.clone().unwrap_or_default());
+		insert_change(&mut fields, "afk_timeout", &self.afk_timeout, u64::to_string);
+		return fields;
+	}
+}
+
+/// Adds `field.old` and `field.new` if the value has changed
+fn insert_change<T, F: Fn(&T) -> String>(fields: &mut HashMap<String, String>, field: &str, change: &Option<Change<T>>, display: F) {
+	if let Some(change) = change {
+		fields.insert(format!("{}.old", field), display(&change.old));
+		fields.insert(format!("{}.new", field), display(&change.new));
+	}
+}
+
+/// Adds `field.added` and `field.removed` as comma separated IDs if either of them is not empty
+fn insert_list<T: ToString>(fields: &mut HashMap<String, String>, field: &str, added: &[T], removed: &[T]) {
+	if added.is_empty() && removed.is_empty() {
+		return;
+	}
+	let join = |list: &[T]| list.iter().map(T::to_string).collect::<Vec<String>>().join(",");
+	fields.insert(format!("{}.added", field), join(added));
+	fields.insert(format!("{}.removed", field), join(removed));
+}
+
+/// Adds `permissions.added` and `permissions.removed` as comma separated permission names
+fn insert_permissions(fields: &mut HashMap<String, String>, added: Permissions, removed: Permissions) {
+	insert_list(fields, "permissions", &added.get_permission_names(), &removed.get_permission_names());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+		return pairs.iter().map(|(key, value)| (String::from(*key), String::from(*value))).collect();
+	}
+
+	#[test]
+	fn unchanged_values_have_no_fields() {
+		let member = MemberDiff { nickname: None, roles_added: Vec::new(), roles_removed: Vec::new() };
+		assert!(member.to_fields().is_empty());
+		let role = RoleDiff {
+			name: None,
+			permissions_added: Permissions::empty(),
+			permissions_removed: Permissions::empty(),
+			position: None,
+			colour: None,
+			hoist: None,
+			mentionable: None,
+		};
+		assert!(role.to_fields().is_empty());
+		let channel = ChannelDiff { name: None, topic: None, position: None, nsfw: None, category: None };
+		assert!(channel.to_fields().is_empty());
+		let guild = GuildDiff { name: None, owner: None, icon: None, afk_timeout: None };
+		assert!(guild.to_fields().is_empty());
+	}
+
+	#[test]
+	fn changed_values_have_old_and_new() {
+		let role = RoleDiff {
+			name: Change::between(String::from("old"), String::from("new")),
+			permissions_added: Permissions::empty(),
+			permissions_removed: Permissions::empty(),
+			position: Change::between(1, 2),
+			colour: Change::between(0xff0000, 0xff),
+			hoist: Change::between(true, true),
+			mentionable: Change::between(false, true),
+		};
+		let expected = fields(&[
+			("name.old", "old"),
+			("name.new", "new"),
+			("position.old", "1"),
+			("position.new", "2"),
+			("colour.old", "ff0000"),
+			("colour.new", "0000ff"),
+			("mentionable.old", "false"),
+			("mentionable.new", "true"),
+		]);
+		assert_eq!(role.to_fields(), expected);
+		let guild = GuildDiff {
+			name: None,
+			owner: Change::between(UserId(1), UserId(2)),
+			icon: None,
+			afk_timeout: Change::between(60, 300),
+		};
+		assert_eq!(guild.to_fields(), fields(&[("owner.old", "1"), ("owner.new", "2"), ("afk_timeout.old", "60"), ("afk_timeout.new", "300")]));
+	}
+
+	#[test]
+	fn removed_values_are_empty() {
+		let member = MemberDiff {
+			nickname: Change::between(Some(String::from("nick")), None),
+			roles_added: Vec::new(),
+			roles_removed: vec![RoleId(1), RoleId(2)],
+		};
+		assert_eq!(member.to_fields(), fields(&[("nickname.old", "nick"), ("nickname.new", ""), ("roles.added", ""), ("roles.removed", "1,2")]));
+		let channel = ChannelDiff {
+			name: None,
+			topic: Change::between(Some(String::from("topic")), None),
+			position: None,
+			nsfw: None,
+			category: Change::between(Some(ChannelId(5)), None),
+		};
+		assert_eq!(channel.to_fields(), fields(&[("topic.old", "topic"), ("topic.new", ""), ("category.old", "5"), ("category.new", "")]));
+	}
+
+	#[test]
+	fn permissions_are_listed_by_name() {
+		let role = RoleDiff {
+			name: None,
+			permissions_added: Permissions::KICK_MEMBERS | Permissions::BAN_MEMBERS,
+			permissions_removed: Permissions::empty(),
+			position: None,
+			colour: None,
+			hoist: None,
+			mentionable: None,
+		};
+		let listed = role.to_fields();
+		let mut added: Vec<&str> = listed["permissions.added"].split(',').collect();
+		added.sort_unstable();
+		assert_eq!(added, vec!["Ban Members", "Kick Members"]);
+		assert_eq!(listed["permissions.removed"], "");
+	}
+}
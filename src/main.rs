@@ -7,34 +7,43 @@ mod utilities;
 mod bot_events;
 mod types;
 mod commands;
+mod diffs;
+mod config;
+mod migrations;
+mod storage;
+mod output;
+mod deletions;
+mod components;
+mod slash_commands;
+mod custom_commands;
 use serenity::{
-	client::{
-		Context,
-		bridge::gateway::GatewayIntents,
-	},
+	client::Context,
 	framework::standard::macros::{group, hook},
 	model::channel::Message,
 };
-use yttrium_key_base::environment::Environment;
 use types::*;
 use commands::*;
 use utilities::*;
 
 #[group]
 #[checks(is_guild_admin)]
-#[commands(execute, add, remove, show, event_add, event_remove, event_show, prefix, admin, error_channel)]
+#[commands(execute, add, remove, show, event_add, event_remove, event_show, prefix, admin, error_channel, usage, mentions, reply, slash_add, slash_remove)]
 struct General;
 
+#[group]
+#[prefixes("db")]
+#[checks(is_guild_admin)]
+#[commands(db_list, db_get, db_set, db_delete, db_clear, db_drop, db_describe, db_export)]
+struct Databases;
+
 #[hook]
 async fn normal_message_hook(context: &Context, message: &Message) {
-	let guild_id = message.guild_id.unwrap().to_string();
+	let guild_id = message.guild_id.unwrap();
 	let lock = context.data.read().await;
 	let db = lock.get::<Database>().unwrap();
-	let query = sqlx::query!("SELECT trigger, code FROM triggers WHERE guild_id = ?", guild_id);
-	let result = query.fetch_all(db).await.unwrap();
-	for row in result {
-		let trigger = row.trigger;
-		let code = row.code;
+	let result = db.get_triggers(&guild_id.to_string()).await.unwrap();
+	drop(lock);
+	for (trigger, code) in result {
 		//Starting with nothing: starting literal
 		//Starting with `&`: literal
 		//Starting with `?`: regex
@@ -42,24 +51,14 @@ async fn normal_message_hook(context: &Context, message: &Message) {
 		if let Some(result) = match_engine::check_match(&message.content, trigger_type) {
 			let parameter = result.rest;
 			let trigger = result.matched;
-			let data = context.data.read().await;
-			let pool = data.get::<Database>().unwrap();
-			let db_manager = databases::SqlDatabaseManager::new(message.guild_id.unwrap(), pool);
 			let event_info = yttrium_key_base::environment::events::MessageEventInfo::new(message.channel_id, message.id, message.author.id, parameter, trigger);
 			let event = yttrium_key_base::environment::events::EventType::Message(event_info);
-			let environment = Environment::new(event, message.guild_id.unwrap(), context, db_manager);
-			let keys = lock.get::<KeyList>().unwrap();
-			let result = yttrium::interpret_string(code.clone(), keys, environment).await;
-			match result {
-				Ok(result) => {
-					utilities::send_result(&context, result).await;
+			let channel_id = message.channel_id;
+			utilities::run_script(context, code, event, guild_id, Some(message.clone()), move |context, error| async move {
+				if let yttrium::errors_and_warns::Error::InterpretationError(error) = error {
+					channel_id.say(&context.http, format!("An error happened during interpretation: `{}`", error)).await.unwrap();
 				}
-				Err(error) => {
-					if let yttrium::errors_and_warns::Error::InterpretationError(error) = error {
-						message.channel_id.say(&context.http, format!("An error happened during interpretation: `{}`", error)).await.unwrap();
-					}
-				}
-			}
+			}).await;
 			return;
 		}
 	}
@@ -67,19 +66,69 @@ async fn normal_message_hook(context: &Context, message: &Message) {
 
 #[tokio::main]
 async fn main() {
+	let config;
+	match config::Config::load() {
+		Ok(loaded) => {
+			config = loaded;
+		}
+		Err(error) => {
+			eprintln!("{}", error);
+			std::process::exit(1);
+		}
+	}
+	tracing_subscriber::fmt().with_max_level(config.tracing_level().unwrap()).init();
+	let storage;
+	match storage::connect(&config.database_url).await {
+		Ok(connected) => {
+			storage = connected;
+		}
+		Err(error) => {
+			tracing::error!("Could not connect to the database: {}", error);
+			std::process::exit(1);
+		}
+	}
+	if let Err(error) = storage.migrate().await {
+		tracing::error!("{}", error);
+		std::process::exit(1);
+	}
+	if config.migrate_only {
+		println!("The database is up to date with schema version {}", storage.schema_version());
+		return;
+	}
+	let script_storage;
+	match databases::ScriptStorage::start(&config.database_url) {
+		Ok(started) => {
+			script_storage = started;
+		}
+		Err(error) => {
+			tracing::error!("Could not connect to the database: {}", error);
+			std::process::exit(1);
+		}
+	}
 	let framework = serenity::framework::StandardFramework::new().configure(|config| {
 		return config.dynamic_prefix(|context, message| Box::pin(async move {
 			let lock = context.data.read().await;
 			let db = lock.get::<Database>().unwrap();
-			return Some(utilities::get_guild_prefix(&message.guild_id.unwrap().to_string(), db).await);
+			let default_prefix = &lock.get::<BotConfig>().unwrap().default_prefix;
+			return Some(utilities::get_guild_prefix(&message.guild_id.unwrap().to_string(), default_prefix, db).await);
 		})).prefix("");
-	}).group(&GENERAL_GROUP).normal_message(normal_message_hook);
-	let mut client = serenity::Client::builder(env!("DISCORD_TOKEN")).intents(GatewayIntents::all()).framework(framework).event_handler(bot_events::Handler).await.unwrap();
+	}).group(&GENERAL_GROUP).group(&DATABASES_GROUP).normal_message(normal_message_hook);
+	let handler_lifetime = Some(config.handler_lifetime).filter(|lifetime| *lifetime > 0).map(std::time::Duration::from_secs);
+	tokio::spawn(storage::sweep_expired_keys(std::sync::Arc::clone(&storage), std::time::Duration::from_secs(config.key_sweep_interval), handler_lifetime));
+	let mut builder = serenity::Client::builder(&config.token).intents(config.gateway_intents().unwrap()).framework(framework).event_handler(bot_events::Handler::default());
+	if let Some(application_id) = config.application_id {
+		builder = builder.application_id(application_id);
+	}
+	let mut client = builder.await.unwrap();
+	let deletion_queue = std::sync::Arc::new(tokio::sync::Notify::new());
+	tokio::spawn(deletions::run(std::sync::Arc::clone(&client.cache_and_http.http), std::sync::Arc::clone(&storage), std::sync::Arc::clone(&deletion_queue)));
 	let mut bot_data = client.data.write().await;
-	let data = sqlx::SqlitePool::connect(env!("DATABASE_URL")).await.unwrap();
-	bot_data.insert::<Database>(data);
+	bot_data.insert::<Database>(storage);
+	bot_data.insert::<ScriptDatabase>(std::sync::Arc::new(script_storage));
+	bot_data.insert::<DeletionQueue>(deletion_queue);
 	let keys = yttrium::key_loader::load_keys();
 	bot_data.insert::<KeyList>(keys);
+	bot_data.insert::<BotConfig>(config);
 	std::mem::drop(bot_data);
 	client.start().await.unwrap();
-}
\ No newline at end of file
+}
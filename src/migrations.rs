@@ -0,0 +1,57 @@
+use sqlx::migrate::{
+	Migrate,
+	MigrateError,
+	Migrator,
+};
+
+/// Applies every migration the database doesn't have yet
+/// Refuses to touch a database whose schema was created by a newer version of the bot
+pub async fn run<DB>(migrator: &Migrator, pool: &sqlx::Pool<DB>) -> Result<(), MigrationError> where DB: sqlx::Database, DB::Connection: Migrate {
+	let mut connection = pool.acquire().await.map_err(MigrateError::from)?;
+	connection.ensure_migrations_table().await?;
+	let applied = connection.list_applied_migrations().await?;
+	std::mem::drop(connection);
+	let binary_version = latest_version(migrator);
+	if let Some(database_version) = applied.iter().map(|migration| migration.version).max() {
+		if database_version > binary_version {
+			return Err(MigrationError::SchemaTooNew { database_version, binary_version });
+		}
+	}
+	migrator.run(pool).await?;
+	return Ok(());
+}
+
+/// The version of the newest migration in `migrator`, 0 if there are none
+pub fn latest_version(migrator: &Migrator) -> i64 {
+	return migrator.iter().map(|migration| migration.version).max().unwrap_or(0);
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+	Migrate(MigrateError),
+	SchemaTooNew {
+		database_version: i64,
+		binary_version: i64,
+	},
+}
+
+impl From<MigrateError> for MigrationError {
+	fn from(error: MigrateError) -> Self {
+		return Self::Migrate(error);
+	}
+}
+
+impl std::fmt::Display for MigrationError {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Migrate(error) => {
+				return write!(formatter, "could not migrate the database: {}", error);
+			}
+			Self::SchemaTooNew { database_version, binary_version } => {
+				return write!(formatter, "the database schema (version {}) is newer than this binary supports (version {}), refusing to start", database_version, binary_version);
+			}
+		}
+	}
+}
+
+impl std::error::Error for MigrationError {}
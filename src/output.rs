@@ -0,0 +1,691 @@
+use serenity::{
+	builder::{
+		CreateComponents,
+		CreateEmbed,
+		CreateMessage,
+		EditMessage,
+		ParseValue,
+	},
+	http::AttachmentType,
+	model::{
+		channel::{
+			Channel,
+			Message,
+		},
+		guild::PremiumTier,
+		id::{
+			ChannelId,
+			GuildId,
+			MessageId,
+			UserId,
+		},
+	},
+	prelude::Context,
+};
+use crate::storage::OutputSettings;
+
+/// The longest message Discord accepts
+pub const MESSAGE_LIMIT: usize = 2000;
+const FENCE: &str = "```";
+
+/// Splits text into chunks no longer than `limit` bytes, which is never less than what Discord counts
+/// Chunks end on line breaks where possible, and a code block split between chunks is closed at the end of one and opened again at the start of the next
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+	let mut chunks = Vec::new();
+	let mut current = String::new();
+	//The fence that opened the code block the current line is in, used to open it again in the next chunk
+	let mut open_fence: Option<String> = None;
+	//Length of the reopened fence at the start of the current chunk, a chunk with nothing after it isn't worth sending
+	let mut chunk_start = 0;
+	for line in text.split_inclusive('\n') {
+		let mut rest = line;
+		while !rest.is_empty() {
+			let mut available = limit.saturating_sub(current.len());
+			if open_fence.is_some() {
+				available = available.saturating_sub(FENCE.len() + 1);
+			}
+			if rest.len() <= available {
+				current.push_str(rest);
+				rest = "";
+			} else if current.len() > chunk_start {
+				chunk_start = finish_chunk(&mut current, open_fence.as_deref(), &mut chunks);
+			} else {
+				//The line doesn't fit even in an empty chunk
+				let cut = split_point(rest, available);
+				current.push_str(&rest[..cut]);
+				rest = &rest[cut..];
+				chunk_start = finish_chunk(&mut current, open_fence.as_deref(), &mut chunks);
+			}
+		}
+		let trimmed = line.trim();
+		if is_fence(trimmed, open_fence.is_some()) {
+			if open_fence.is_some() {
+				open_fence = None;
+			} else if trimmed.len() <= limit / 4 {
+				open_fence = Some(String::from(trimmed));
+			} else {
+				//A fence line that long would take up most of every chunk
+				open_fence = Some(String::from(FENCE));
+			}
+		}
+	}
+	if current.len() > chunk_start && !current.trim().is_empty() {
+		chunks.push(current);
+	}
+	return chunks;
+}
+
+/// Whether a trimmed line opens or closes a code block, backticks later in a line or around inline code don't
+/// A closing fence is only backticks, an opening one can name the language but can't have another backtick
+fn is_fence(line: &str, closing: bool) -> bool {
+	if !line.starts_with(FENCE) {
+		return false;
+	}
+	let rest = line.trim_start_matches('`');
+	if closing {
+		return rest.is_empty();
+	}
+	return !rest.contains('`');
+}
+
+/// Moves the chunk into `chunks`, closing an open code block and opening it again in the now empty `chunk`
+/// Returns the length of what was put in `chunk`
+fn finish_chunk(chunk: &mut String, open_fence: Option<&str>, chunks: &mut Vec<String>) -> usize {
+	if let Some(fence) = open_fence {
+		if !chunk.ends_with('\n') {
+			chunk.push('\n');
+		}
+		chunk.push_str(FENCE);
+		chunks.push(std::mem::take(chunk));
+		chunk.push_str(fence);
+		chunk.push('\n');
+	} else {
+		chunks.push(std::mem::take(chunk));
+	}
+	return chunk.len();
+}
+
+/// Where to cut text that has to be split in the middle of a line
+/// Prefers the last whitespace before `max`, and always cuts at a character boundary and after at least one character
+fn split_point(text: &str, max: usize) -> usize {
+	let mut boundary = std::cmp::min(max, text.len());
+	while !text.is_char_boundary(boundary) {
+		boundary -= 1;
+	}
+	if boundary == 0 {
+		return text.chars().next().map(|character| character.len_utf8()).unwrap_or(text.len());
+	}
+	match text[..boundary].rfind(char::is_whitespace) {
+		Some(position) if position > 0 => {
+			return position + 1;
+		}
+		_ => {
+			return boundary;
+		}
+	}
+}
+
+/// How every message of one output is sent
+#[derive(Debug, Clone, Copy)]
+pub struct SendOptions {
+	/// Text longer than this many bytes is sent as a file, 0 never sends a file
+	pub file_threshold: usize,
+	/// The message the first message of the output replies to
+	pub reply_to: Option<MessageId>,
+	pub settings: OutputSettings,
+}
+
+impl SendOptions {
+	/// Sets the allowed mentions of a message, and the reply if it is the first message of the output
+	pub fn apply<'a, 'b>(&self, channel: ChannelId, message: &'b mut CreateMessage<'a>, first: bool) -> &'b mut CreateMessage<'a> {
+		let settings = self.settings;
+		let replying = first && self.reply_to.is_some();
+		message.allowed_mentions(|mentions| {
+			mentions.empty_parse();
+			if settings.allow_everyone {
+				mentions.parse(ParseValue::Everyone);
+			}
+			if settings.allow_roles {
+				mentions.parse(ParseValue::Roles);
+			}
+			if settings.allow_users {
+				mentions.parse(ParseValue::Users);
+			}
+			return mentions.replied_user(replying && settings.ping_on_reply);
+		});
+		if let (true, Some(reply_to)) = (first, self.reply_to) {
+			message.reference_message((channel, reply_to));
+		}
+		return message;
+	}
+}
+
+impl Default for SendOptions {
+	/// No file fallback, no reply and the default mention policy
+	fn default() -> Self {
+		return Self {
+			file_threshold: 0,
+			reply_to: None,
+			settings: OutputSettings::default(),
+		};
+	}
+}
+
+/// Sends text to the channel, split into as many messages as needed
+/// `components` go on the last message
+/// Returns every message sent, in order
+pub async fn send_text(context: &Context, channel: ChannelId, text: &str, components: Option<CreateComponents>, options: &SendOptions) -> serenity::Result<Vec<Message>> {
+	let mut components = components;
+	if options.file_threshold != 0 && text.len() > options.file_threshold {
+		let file = AttachmentType::Bytes { data: text.as_bytes().into(), filename: String::from("output.txt") };
+		let message = channel.send_files(context, vec![file], |message| {
+			return fill(options.apply(channel, message, true), Vec::new(), components).content("The output was too long to send as a message");
+		}).await?;
+		return Ok(vec![message]);
+	}
+	let chunks = split_message(text, MESSAGE_LIMIT);
+	let chunk_count = chunks.len();
+	let mut messages = Vec::new();
+	for (index, chunk) in chunks.into_iter().enumerate() {
+		let first = messages.is_empty();
+		let chunk_components;
+		if index + 1 == chunk_count {
+			chunk_components = components.take();
+		} else {
+			chunk_components = None;
+		}
+		messages.push(channel.send_message(context, |message| {
+			return fill(options.apply(channel, message, first), Vec::new(), chunk_components).content(chunk);
+		}).await?);
+	}
+	return Ok(messages);
+}
+
+/// Discord allows at most this many files and embeds in a message
+const FILES_PER_MESSAGE: usize = 10;
+const EMBEDS_PER_MESSAGE: usize = 10;
+
+/// A file attached by a script, checked and ready to upload
+#[derive(Debug)]
+pub struct OutputFile {
+	pub filename: String,
+	pub data: Vec<u8>,
+}
+
+impl OutputFile {
+	/// Checks the size of the attachment and works out its type
+	/// The filename gets an extension matching the type if it doesn't have one, since Discord picks how to show a file by its extension
+	pub fn new(attachment: yttrium_key_base::environment::Attachment, size_limit: u64) -> Result<Self, String> {
+		let mut filename = attachment.filename.replace(|character| character == '/' || character == '\\', "_");
+		if filename.trim().is_empty() {
+			filename = String::from("file");
+		}
+		if attachment.data.len() as u64 > size_limit {
+			return Err(format!("`{}` is {} bytes long, the limit is {} bytes", filename, attachment.data.len(), size_limit));
+		}
+		//The contents are trusted over the declared type, the declared type over guessing
+		let content_type = sniff_content_type(&attachment.data).or_else(|| attachment.content_type.as_deref().and_then(known_content_type)).unwrap_or_else(|| {
+			if std::str::from_utf8(&attachment.data).is_ok() {
+				return "text/plain";
+			}
+			return "application/octet-stream";
+		});
+		let extensions = extensions_for(content_type);
+		let current_extension = filename.rsplit_once('.').map(|(_, extension)| extension.to_lowercase());
+		if let Some(preferred) = extensions.first() {
+			if !current_extension.map(|extension| extensions.contains(&extension.as_str())).unwrap_or(false) {
+				filename = format!("{}.{}", filename, preferred);
+			}
+		}
+		return Ok(Self { filename, data: attachment.data });
+	}
+}
+
+/// Recognizes common file types by their first bytes
+fn sniff_content_type(data: &[u8]) -> Option<&'static str> {
+	if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+		return Some("image/png");
+	}
+	if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+		return Some("image/jpeg");
+	}
+	if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+		return Some("image/gif");
+	}
+	if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+		return Some("image/webp");
+	}
+	if data.starts_with(b"%PDF-") {
+		return Some("application/pdf");
+	}
+	if data.starts_with(b"PK\x03\x04") {
+		return Some("application/zip");
+	}
+	return None;
+}
+
+/// Maps a declared content type onto one of the types this module knows, ignoring parameters like `charset`
+fn known_content_type(declared: &str) -> Option<&'static str> {
+	let essence = declared.split(';').next().unwrap_or("").trim().to_lowercase();
+	let known = ["image/png", "image/jpeg", "image/gif", "image/webp", "application/pdf", "application/zip", "text/plain", "text/csv", "application/json"];
+	return known.iter().find(|known| **known == essence).copied();
+}
+
+/// Extensions Discord recognizes for the type, the preferred one first
+fn extensions_for(content_type: &str) -> &'static [&'static str] {
+	match content_type {
+		"image/png" => {
+			return &["png"];
+		}
+		"image/jpeg" => {
+			return &["jpg", "jpeg"];
+		}
+		"image/gif" => {
+			return &["gif"];
+		}
+		"image/webp" => {
+			return &["webp"];
+		}
+		"application/pdf" => {
+			return &["pdf"];
+		}
+		"application/zip" => {
+			return &["zip"];
+		}
+		"text/csv" => {
+			return &["csv"];
+		}
+		"application/json" => {
+			return &["json"];
+		}
+		"text/plain" => {
+			return &["txt", "log", "md", "csv", "json"];
+		}
+		_ => {
+			return &[];
+		}
+	}
+}
+
+/// The largest upload Discord accepts in a guild with the boost tier
+pub fn guild_upload_limit(tier: PremiumTier) -> u64 {
+	match tier {
+		PremiumTier::Tier2 => {
+			return 50 * 1024 * 1024;
+		}
+		PremiumTier::Tier3 => {
+			return 100 * 1024 * 1024;
+		}
+		_ => {
+			return 8 * 1024 * 1024;
+		}
+	}
+}
+
+/// Sends the embeds and files, as few messages as Discord's limits allow
+/// `components` go on the last message, `first` is whether nothing of the output has been sent yet, so that the reply goes on the right message
+/// Discord limits the size of a whole upload, so the files of a message add up to at most `size_limit` bytes
+pub async fn send_rich(context: &Context, channel: ChannelId, files: Vec<OutputFile>, embeds: Vec<CreateEmbed>, components: Option<CreateComponents>, options: &SendOptions, first: bool, size_limit: u64) -> serenity::Result<Vec<Message>> {
+	let mut files = files;
+	let mut embeds = embeds;
+	let mut components = components;
+	let mut messages = Vec::new();
+	while !files.is_empty() || !embeds.is_empty() {
+		let file_count = batch_size(&files, size_limit);
+		let batch_files = files.drain(..file_count).map(|file| AttachmentType::Bytes { data: file.data.into(), filename: file.filename }).collect::<Vec<AttachmentType>>();
+		let embed_count = std::cmp::min(embeds.len(), EMBEDS_PER_MESSAGE);
+		let batch_embeds = embeds.drain(..embed_count).collect::<Vec<CreateEmbed>>();
+		let batch_components;
+		if files.is_empty() && embeds.is_empty() {
+			batch_components = components.take();
+		} else {
+			batch_components = None;
+		}
+		let is_first = first && messages.is_empty();
+		if batch_files.is_empty() {
+			messages.push(channel.send_message(context, |message| {
+				return fill(options.apply(channel, message, is_first), batch_embeds, batch_components);
+			}).await?);
+		} else {
+			messages.push(channel.send_files(context, batch_files, |message| {
+				return fill(options.apply(channel, message, is_first), batch_embeds, batch_components);
+			}).await?);
+		}
+	}
+	return Ok(messages);
+}
+
+/// How many of the files go on the next message, every file fits on its own since [OutputFile::new] checks the size
+fn batch_size(files: &[OutputFile], size_limit: u64) -> usize {
+	let mut total = 0;
+	let mut count = 0;
+	for file in files.iter().take(FILES_PER_MESSAGE) {
+		total += file.data.len() as u64;
+		if count > 0 && total > size_limit {
+			break;
+		}
+		count += 1;
+	}
+	return count;
+}
+
+/// Adds embeds and components to a message
+fn fill<'a, 'b>(message: &'b mut CreateMessage<'a>, embeds: Vec<CreateEmbed>, components: Option<CreateComponents>) -> &'b mut CreateMessage<'a> {
+	if !embeds.is_empty() {
+		message.set_embeds(embeds);
+	}
+	if let Some(components) = components {
+		message.set_components(components);
+	}
+	return message;
+}
+
+/// Replaces the content, embeds and components of a message sent by the bot with new output
+/// An edit is a single message, so the output has to fit in it
+pub async fn update_message(context: &Context, channel: ChannelId, message: MessageId, text: &str, embeds: Vec<CreateEmbed>, components: Option<CreateComponents>) -> Result<Message, String> {
+	if text.chars().count() > MESSAGE_LIMIT {
+		return Err(format!("the output is longer than the {} characters a message can have", MESSAGE_LIMIT));
+	}
+	if embeds.len() > EMBEDS_PER_MESSAGE {
+		return Err(format!("a message can have at most {} embeds", EMBEDS_PER_MESSAGE));
+	}
+	let result = channel.edit_message(context, message, |edit: &mut EditMessage| {
+		edit.content(text).set_embeds(embeds);
+		if let Some(components) = components {
+			edit.set_components(components);
+		}
+		return edit;
+	}).await;
+	return result.map_err(|error| error.to_string());
+}
+
+/// Where script output goes, resolved from the target set by the script
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+	Channel(ChannelId),
+	/// Direct message to a member of the guild
+	User(UserId),
+	/// Reply to the message that started the script
+	Reply(ChannelId, MessageId),
+	/// Edit of the bot's message that started the script, like one with a button that was clicked
+	Update(ChannelId, MessageId),
+}
+
+impl Target {
+	/// Resolves a target, which can be a channel mention, ID or name, a thread ID, a user mention or ID, `reply` or `update`
+	/// Only channels and members of `guild_id` are accepted, so that scripts can't send messages to other servers
+	/// `origin` is the message that started the script, [None] for events
+	pub async fn resolve(context: &Context, guild_id: GuildId, target: &str, origin: Option<&Message>) -> Result<Self, String> {
+		let target = target.trim();
+		if target.eq_ignore_ascii_case("reply") {
+			match origin {
+				Some(message) => {
+					return Ok(Self::Reply(message.channel_id, message.id));
+				}
+				None => {
+					return Err(String::from("`reply` only works for scripts started by a message"));
+				}
+			}
+		}
+		if target.eq_ignore_ascii_case("update") {
+			match origin {
+				Some(message) if message.author.id == context.cache.current_user_id().await => {
+					return Ok(Self::Update(message.channel_id, message.id));
+				}
+				_ => {
+					return Err(String::from("`update` only works for scripts started from a message sent by the bot"));
+				}
+			}
+		}
+		if let Some(id) = mention_id(target, "<#") {
+			return Self::guild_channel(context, guild_id, ChannelId(id)).await;
+		}
+		if let Some(id) = mention_id(target, "<@!").or_else(|| mention_id(target, "<@")) {
+			return Self::guild_member(context, guild_id, UserId(id)).await;
+		}
+		if let Ok(id) = target.parse::<u64>() {
+			//An ID alone doesn't say what it points to, channels and threads are checked before members
+			if let Ok(channel) = Self::guild_channel(context, guild_id, ChannelId(id)).await {
+				return Ok(channel);
+			}
+			if let Ok(user) = Self::guild_member(context, guild_id, UserId(id)).await {
+				return Ok(user);
+			}
+			return Err(format!("`{}` isn't a channel, thread or member of this server", id));
+		}
+		let name = target.trim_start_matches('#');
+		match guild_id.channels(context).await {
+			Ok(channels) => {
+				match channels.values().find(|channel| channel.name == name) {
+					Some(channel) => {
+						return Ok(Self::Channel(channel.id));
+					}
+					None => {
+						return Err(format!("there is no channel called `{}`", name));
+					}
+				}
+			}
+			Err(error) => {
+				return Err(format!("could not get the channels of this server: {}", error));
+			}
+		}
+	}
+
+	async fn guild_channel(context: &Context, guild_id: GuildId, channel_id: ChannelId) -> Result<Self, String> {
+		match channel_id.to_channel(context).await {
+			Ok(Channel::Guild(channel)) if channel.guild_id == guild_id => {
+				return Ok(Self::Channel(channel.id));
+			}
+			Ok(_) => {
+				return Err(format!("<#{}> isn't a channel of this server", channel_id));
+			}
+			Err(error) => {
+				return Err(format!("could not find channel `{}`: {}", channel_id, error));
+			}
+		}
+	}
+
+	async fn guild_member(context: &Context, guild_id: GuildId, user_id: UserId) -> Result<Self, String> {
+		match guild_id.member(context, user_id).await {
+			Ok(_) => {
+				return Ok(Self::User(user_id));
+			}
+			Err(error) => {
+				return Err(format!("could not find member `{}`: {}", user_id, error));
+			}
+		}
+	}
+
+	/// The channel to send to, opening a DM channel for users
+	pub async fn channel(&self, context: &Context) -> serenity::Result<ChannelId> {
+		match self {
+			Self::Channel(channel) | Self::Reply(channel, _) | Self::Update(channel, _) => {
+				return Ok(*channel);
+			}
+			Self::User(user) => {
+				return Ok(user.create_dm_channel(context).await?.id);
+			}
+		}
+	}
+
+	pub fn reply_to(&self) -> Option<MessageId> {
+		if let Self::Reply(_, message) = self {
+			return Some(*message);
+		}
+		return None;
+	}
+}
+
+impl std::fmt::Display for Target {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Channel(channel) => {
+				return write!(formatter, "<#{}>", channel);
+			}
+			Self::User(user) => {
+				return write!(formatter, "a DM to <@{}>", user);
+			}
+			Self::Reply(channel, _) => {
+				return write!(formatter, "a reply in <#{}>", channel);
+			}
+			Self::Update(channel, _) => {
+				return write!(formatter, "an edited message in <#{}>", channel);
+			}
+		}
+	}
+}
+
+/// Returns the ID in a mention like `<#123>`, [None] if `text` isn't a mention starting with `prefix`
+fn mention_id(text: &str, prefix: &str) -> Option<u64> {
+	return text.strip_prefix(prefix)?.strip_suffix('>')?.parse().ok();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use yttrium_key_base::environment::Attachment;
+
+	fn attachment(filename: &str, content_type: Option<&str>, data: &[u8]) -> Attachment {
+		return Attachment { filename: String::from(filename), content_type: content_type.map(String::from), data: data.to_vec() };
+	}
+
+	fn file(size: usize) -> OutputFile {
+		return OutputFile { filename: String::from("file"), data: vec![0; size] };
+	}
+
+	#[test]
+	fn short_text_is_one_chunk() {
+		assert_eq!(split_message("hello\nworld", MESSAGE_LIMIT), vec!["hello\nworld"]);
+		assert!(split_message("", MESSAGE_LIMIT).is_empty());
+		assert!(split_message(" \n ", MESSAGE_LIMIT).is_empty());
+	}
+
+	#[test]
+	fn chunks_stay_within_the_limit_and_end_on_line_breaks() {
+		let line = format!("{}\n", "a".repeat(99));
+		let text = line.repeat(50);
+		let chunks = split_message(&text, MESSAGE_LIMIT);
+		assert_eq!(chunks.len(), 3);
+		for chunk in &chunks {
+			assert!(chunk.len() <= MESSAGE_LIMIT);
+			assert!(chunk.ends_with('\n'));
+		}
+		assert_eq!(chunks.concat(), text);
+	}
+
+	#[test]
+	fn long_lines_are_cut_at_whitespace() {
+		let text = "word ".repeat(500);
+		let chunks = split_message(&text, MESSAGE_LIMIT);
+		assert_eq!(chunks.len(), 2);
+		assert!(chunks[0].len() <= MESSAGE_LIMIT);
+		assert!(chunks[0].ends_with(' '));
+		assert_eq!(chunks.concat(), text);
+	}
+
+	#[test]
+	fn code_blocks_are_closed_and_opened_again() {
+		let text = format!("```rust\n{}```\nafter\n", "let x = 1;\n".repeat(300));
+		let chunks = split_message(&text, MESSAGE_LIMIT);
+		assert!(chunks.len() > 1);
+		for (index, chunk) in chunks.iter().enumerate() {
+			assert!(chunk.len() <= MESSAGE_LIMIT);
+			assert!(chunk.starts_with("```rust\n"));
+			if index + 1 < chunks.len() {
+				assert!(chunk.ends_with("\n```"));
+			}
+		}
+		assert!(chunks.last().unwrap().ends_with("```\nafter\n"));
+	}
+
+	#[test]
+	fn only_fences_at_the_start_of_a_line_count() {
+		//Neither of these opens a code block, so nothing is closed or opened again between the chunks
+		let inline = format!("use ```code``` here\n{}", "text\n".repeat(500));
+		let chunks = split_message(&inline, MESSAGE_LIMIT);
+		assert!(chunks.len() > 1);
+		assert!(chunks[1..].iter().all(|chunk| !chunk.contains(FENCE)));
+		let one_line = format!("```inline```\n{}", "text\n".repeat(500));
+		let chunks = split_message(&one_line, MESSAGE_LIMIT);
+		assert!(chunks[1..].iter().all(|chunk| !chunk.contains(FENCE)));
+		assert!(is_fence("```", true));
+		assert!(is_fence("```py", false));
+		assert!(!is_fence("```py", true));
+		assert!(!is_fence("a ```", false));
+	}
+
+	#[test]
+	fn cuts_are_on_character_boundaries() {
+		let text = "é".repeat(1500);
+		let chunks = split_message(&text, MESSAGE_LIMIT);
+		assert_eq!(chunks.len(), 2);
+		assert!(chunks.iter().all(|chunk| chunk.len() <= MESSAGE_LIMIT));
+		assert_eq!(chunks.concat(), text);
+		assert_eq!(split_point("aé", 2), 1);
+		assert_eq!(split_point("éa", 1), 2);
+		assert_eq!(split_point("ab cd", 4), 3);
+		assert_eq!(split_point("abcd", 2), 2);
+	}
+
+	#[test]
+	fn types_are_recognized_by_their_first_bytes() {
+		assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n rest"), Some("image/png"));
+		assert_eq!(sniff_content_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+		assert_eq!(sniff_content_type(b"GIF89a rest"), Some("image/gif"));
+		assert_eq!(sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+		assert_eq!(sniff_content_type(b"%PDF-1.7"), Some("application/pdf"));
+		assert_eq!(sniff_content_type(b"PK\x03\x04"), Some("application/zip"));
+		assert_eq!(sniff_content_type(b"RIFF\0\0\0\0WAVE"), None);
+		assert_eq!(sniff_content_type(b"plain text"), None);
+		assert_eq!(sniff_content_type(b""), None);
+	}
+
+	#[test]
+	fn declared_types_ignore_case_and_parameters() {
+		assert_eq!(known_content_type("text/csv"), Some("text/csv"));
+		assert_eq!(known_content_type("Text/Plain; charset=utf-8"), Some("text/plain"));
+		assert_eq!(known_content_type(" application/json "), Some("application/json"));
+		assert_eq!(known_content_type("text/html"), None);
+		assert_eq!(known_content_type(""), None);
+	}
+
+	#[test]
+	fn files_over_the_limit_are_refused() {
+		assert!(OutputFile::new(attachment("big.txt", None, &[b'a'; 11]), 10).is_err());
+		assert!(OutputFile::new(attachment("fits.txt", None, &[b'a'; 10]), 10).is_ok());
+	}
+
+	#[test]
+	fn filenames_get_an_extension_matching_the_contents() {
+		let png = OutputFile::new(attachment("chart", Some("text/plain"), b"\x89PNG\r\n\x1a\n"), 100).unwrap();
+		assert_eq!(png.filename, "chart.png");
+		let jpeg = OutputFile::new(attachment("photo.JPEG", None, &[0xFF, 0xD8, 0xFF]), 100).unwrap();
+		assert_eq!(jpeg.filename, "photo.JPEG");
+		let csv = OutputFile::new(attachment("export", Some("text/csv; charset=utf-8"), b"a,b\n1,2"), 100).unwrap();
+		assert_eq!(csv.filename, "export.csv");
+		let text = OutputFile::new(attachment("notes.md", None, b"# notes"), 100).unwrap();
+		assert_eq!(text.filename, "notes.md");
+		let binary = OutputFile::new(attachment("blob.bin", None, &[0, 159, 146, 150]), 100).unwrap();
+		assert_eq!(binary.filename, "blob.bin");
+	}
+
+	#[test]
+	fn filenames_are_made_safe() {
+		let nested = OutputFile::new(attachment("../dir\\name.txt", None, b"text"), 100).unwrap();
+		assert_eq!(nested.filename, ".._dir_name.txt");
+		let unnamed = OutputFile::new(attachment("  ", None, b"text"), 100).unwrap();
+		assert_eq!(unnamed.filename, "file.txt");
+	}
+
+	#[test]
+	fn batches_stay_within_the_count_and_size_limits() {
+		let small = (0..12).map(|_| file(1)).collect::<Vec<OutputFile>>();
+		assert_eq!(batch_size(&small, 100), FILES_PER_MESSAGE);
+		let large = vec![file(6), file(4), file(1)];
+		assert_eq!(batch_size(&large, 10), 2);
+		assert_eq!(batch_size(&large[2..], 10), 1);
+		let full = vec![file(10), file(1)];
+		assert_eq!(batch_size(&full, 10), 1);
+		assert_eq!(batch_size(&[], 10), 0);
+	}
+}
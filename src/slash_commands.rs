@@ -0,0 +1,259 @@
+use serenity::{
+	builder::CreateApplicationCommandOption,
+	framework::standard::Reason,
+	model::{
+		interactions::{
+			InteractionResponseType,
+			application_command::{
+				ApplicationCommand,
+				ApplicationCommandInteraction,
+				ApplicationCommandInteractionDataOption,
+				ApplicationCommandInteractionDataOptionValue,
+				ApplicationCommandOptionType,
+			},
+			autocomplete::AutocompleteInteraction,
+		},
+	},
+	prelude::Context,
+};
+use crate::commands;
+use crate::custom_commands;
+use crate::types::Database;
+use crate::utilities;
+
+/// Discord shows at most this many autocomplete choices, and choices can't be longer than this
+const MAX_CHOICES: usize = 25;
+const MAX_CHOICE_LENGTH: usize = 100;
+
+/// Names of the commands registered by [register], guilds can't define commands with these names
+pub const BUILTIN_COMMANDS: &[&str] = &["add", "remove", "show", "event_add", "event_remove", "event_show", "prefix", "admin", "error_channel"];
+
+/// Registers the management commands as global slash commands, replacing the previously registered set
+pub async fn register(context: &Context) -> serenity::Result<()> {
+	ApplicationCommand::set_global_application_commands(&context.http, |commands| {
+		return commands.create_application_command(|command| {
+			return command.name("add").description("Adds a trigger or replaces its code")
+				.create_option(|option| text_option(option, "trigger", "Text that runs the code", true, false))
+				.create_option(|option| text_option(option, "code", "Code to run", true, false));
+		}).create_application_command(|command| {
+			return command.name("remove").description("Removes a trigger")
+				.create_option(|option| text_option(option, "trigger", "Trigger to remove", true, true));
+		}).create_application_command(|command| {
+			return command.name("show").description("Shows the code of a trigger")
+				.create_option(|option| text_option(option, "trigger", "Trigger to show", true, true));
+		}).create_application_command(|command| {
+			return command.name("event_add").description("Attaches code to an event or replaces it")
+				.create_option(|option| text_option(option, "event", "Event that runs the code", true, true))
+				.create_option(|option| text_option(option, "code", "Code to run", true, false));
+		}).create_application_command(|command| {
+			return command.name("event_remove").description("Removes the code of an event")
+				.create_option(|option| text_option(option, "event", "Event to remove", true, true));
+		}).create_application_command(|command| {
+			return command.name("event_show").description("Shows the code of an event")
+				.create_option(|option| text_option(option, "event", "Event to show", true, true));
+		}).create_application_command(|command| {
+			return command.name("prefix").description("Changes or shows the command prefix")
+				.create_option(|option| text_option(option, "prefix", "New prefix, leave out to show the current one", false, false));
+		}).create_application_command(|command| {
+			return command.name("admin").description("Sets the role that can manage the bot")
+				.create_option(|option| {
+					return option.name("role").description("Leave out to require the Manage Server permission").kind(ApplicationCommandOptionType::Role).required(false);
+				});
+		}).create_application_command(|command| {
+			return command.name("error_channel").description("Sets the channel script errors are sent to")
+				.create_option(|option| {
+					return option.name("channel").description("Leave out to stop sending errors").kind(ApplicationCommandOptionType::Channel).required(false);
+				});
+		});
+	}).await?;
+	return Ok(());
+}
+
+fn text_option<'a>(option: &'a mut CreateApplicationCommandOption, name: &str, description: &str, required: bool, autocomplete: bool) -> &'a mut CreateApplicationCommandOption {
+	return option.name(name).description(description).kind(ApplicationCommandOptionType::String).required(required).set_autocomplete(autocomplete);
+}
+
+/// Runs a slash command through the same functions as its prefix version
+/// Commands that aren't the bot's own were defined by the guild and are open to everyone
+pub async fn run(context: &Context, interaction: ApplicationCommandInteraction) {
+	if !BUILTIN_COMMANDS.contains(&interaction.data.name.as_str()) {
+		custom_commands::run(context, interaction).await;
+		return;
+	}
+	let guild_id;
+	match (interaction.guild_id, interaction.member.as_ref()) {
+		(Some(id), Some(member)) => {
+			if let Err(reason) = utilities::check_guild_admin(context, id, member).await {
+				respond(context, &interaction, &reason_text(reason)).await;
+				return;
+			}
+			guild_id = id;
+		}
+		_ => {
+			respond(context, &interaction, "Commands can only be used in servers").await;
+			return;
+		}
+	}
+	let options = &interaction.data.options;
+	let response;
+	match interaction.data.name.as_str() {
+		"add" => {
+			response = commands::add_trigger(context, guild_id, &text(options, "trigger"), &text(options, "code")).await;
+		}
+		"remove" => {
+			response = commands::remove_trigger(context, guild_id, &text(options, "trigger")).await;
+		}
+		"show" => {
+			response = commands::show_trigger(context, guild_id, &text(options, "trigger")).await;
+		}
+		"event_add" => {
+			response = commands::add_event(context, guild_id, &text(options, "event"), &text(options, "code")).await;
+		}
+		"event_remove" => {
+			response = commands::remove_event(context, guild_id, &text(options, "event")).await;
+		}
+		"event_show" => {
+			response = commands::show_event(context, guild_id, &text(options, "event")).await;
+		}
+		"prefix" => {
+			let new_prefix = text(options, "prefix");
+			if new_prefix.is_empty() {
+				response = commands::set_prefix(context, guild_id, None).await;
+			} else if new_prefix.contains(char::is_whitespace) {
+				response = String::from("The prefix can't contain whitespace");
+			} else {
+				response = commands::set_prefix(context, guild_id, Some(&new_prefix)).await;
+			}
+		}
+		"admin" => {
+			let role;
+			match resolved(options, "role") {
+				Some(ApplicationCommandInteractionDataOptionValue::Role(found)) => {
+					role = Some(found.id);
+				}
+				_ => {
+					role = None;
+				}
+			}
+			response = commands::set_admin_role(context, guild_id, role).await;
+		}
+		"error_channel" => {
+			let channel;
+			match resolved(options, "channel") {
+				Some(ApplicationCommandInteractionDataOptionValue::Channel(found)) => {
+					channel = Some(found.id);
+				}
+				_ => {
+					channel = None;
+				}
+			}
+			response = commands::set_error_channel(context, guild_id, channel).await;
+		}
+		name => {
+			response = format!("`{}` isn't a command of this bot", name);
+		}
+	}
+	respond(context, &interaction, &response).await;
+}
+
+/// Suggests existing triggers and events, and every event name for `event_add`
+pub async fn autocomplete(context: &Context, interaction: AutocompleteInteraction) {
+	let guild_id;
+	match interaction.guild_id {
+		Some(id) => {
+			guild_id = id.to_string();
+		}
+		None => {
+			return;
+		}
+	}
+	let focused;
+	match interaction.data.options.iter().find(|option| option.focused) {
+		Some(option) => {
+			focused = option;
+		}
+		None => {
+			return;
+		}
+	}
+	let typed = focused.value.as_ref().and_then(|value| value.as_str()).unwrap_or_default().to_lowercase();
+	let lock = context.data.read().await;
+	let db = lock.get::<Database>().unwrap();
+	let lookup;
+	match (interaction.data.name.as_str(), focused.name.as_str()) {
+		("remove", "trigger") | ("show", "trigger") => {
+			lookup = db.get_triggers(&guild_id).await.map(|triggers| triggers.into_iter().map(|(trigger, _)| trigger).collect());
+		}
+		("event_add", "event") => {
+			lookup = Ok(utilities::EVENT_NAMES.iter().map(|name| String::from(*name)).collect());
+		}
+		("event_remove", "event") | ("event_show", "event") => {
+			lookup = db.list_events(&guild_id).await;
+		}
+		_ => {
+			return;
+		}
+	}
+	drop(lock);
+	let candidates: Vec<String>;
+	match lookup {
+		Ok(found) => {
+			candidates = found;
+		}
+		Err(error) => {
+			tracing::error!("autocomplete: DB error on guild `{}`: `{}`", guild_id, error);
+			return;
+		}
+	}
+	//Choices longer than Discord's limit can't be suggested at all, cutting them would suggest a different trigger
+	let choices = candidates.into_iter().filter(|candidate| candidate.chars().count() <= MAX_CHOICE_LENGTH && candidate.to_lowercase().contains(&typed)).take(MAX_CHOICES);
+	let result = interaction.create_autocomplete_response(&context.http, |response| {
+		for choice in choices {
+			response.add_string_choice(&choice, &choice);
+		}
+		return response;
+	}).await;
+	if let Err(error) = result {
+		tracing::warn!("Could not send autocomplete choices: `{}`", error);
+	}
+}
+
+pub async fn respond(context: &Context, interaction: &ApplicationCommandInteraction, text: &str) {
+	let result = interaction.create_interaction_response(&context.http, |response| {
+		return response.kind(InteractionResponseType::ChannelMessageWithSource).interaction_response_data(|data| data.content(text));
+	}).await;
+	if let Err(error) = result {
+		tracing::warn!("Could not respond to `/{}`: `{}`", interaction.data.name, error);
+	}
+}
+
+/// The value of a string option, empty if it wasn't given
+fn text(options: &[ApplicationCommandInteractionDataOption], name: &str) -> String {
+	match resolved(options, name) {
+		Some(ApplicationCommandInteractionDataOptionValue::String(value)) => {
+			return value.clone();
+		}
+		_ => {
+			return String::new();
+		}
+	}
+}
+
+fn resolved<'a>(options: &'a [ApplicationCommandInteractionDataOption], name: &str) -> Option<&'a ApplicationCommandInteractionDataOptionValue> {
+	return options.iter().find(|option| option.name == name)?.resolved.as_ref();
+}
+
+fn reason_text(reason: Reason) -> String {
+	match reason {
+		Reason::User(text) => {
+			return text;
+		}
+		Reason::UserAndLog { user, log } => {
+			tracing::error!("{}", log);
+			return user;
+		}
+		_ => {
+			return String::from("You can't use this command");
+		}
+	}
+}
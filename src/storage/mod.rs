@@ -0,0 +1,427 @@
+mod sqlite;
+mod postgres;
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+use serenity::async_trait;
+use crate::config::QuotaLimits;
+use crate::migrations::MigrationError;
+pub use sqlite::SqliteStorage;
+pub use postgres::PostgresStorage;
+
+pub type StorageResult<T> = Result<T, sqlx::Error>;
+
+/// Everything the bot keeps in its database
+/// There is one implementation per supported database, the one in use is picked from the database URL at startup
+#[async_trait]
+pub trait Storage: Send + Sync {
+	/// Applies pending migrations for this database
+	async fn migrate(&self) -> Result<(), MigrationError>;
+	/// The version of the newest migration this binary has for this database
+	fn schema_version(&self) -> i64;
+
+	/// Returns every `(trigger, code)` pair in the guild
+	async fn get_triggers(&self, guild_id: &str) -> StorageResult<Vec<(String, String)>>;
+	async fn get_trigger(&self, guild_id: &str, trigger: &str) -> StorageResult<Option<String>>;
+	/// Adds the trigger, replacing the code if it already exists
+	async fn set_trigger(&self, guild_id: &str, trigger: &str, code: &str) -> StorageResult<()>;
+	/// Returns `false` if the trigger didn't exist
+	async fn remove_trigger(&self, guild_id: &str, trigger: &str) -> StorageResult<bool>;
+
+	/// Returns the names of every event with code in the guild
+	async fn list_events(&self, guild_id: &str) -> StorageResult<Vec<String>>;
+	async fn get_event(&self, guild_id: &str, event: &str) -> StorageResult<Option<String>>;
+	/// Adds the event, replacing the code if it already exists
+	async fn set_event(&self, guild_id: &str, event: &str, code: &str) -> StorageResult<()>;
+	/// Returns `false` if the event didn't exist
+	async fn remove_event(&self, guild_id: &str, event: &str) -> StorageResult<bool>;
+
+	async fn get_prefix(&self, guild_id: &str) -> StorageResult<Option<String>>;
+	async fn set_prefix(&self, guild_id: &str, prefix: &str) -> StorageResult<bool>;
+	async fn get_admin_role(&self, guild_id: &str) -> StorageResult<Option<String>>;
+	async fn set_admin_role(&self, guild_id: &str, admin_role: Option<&str>) -> StorageResult<bool>;
+	async fn get_error_channel(&self, guild_id: &str) -> StorageResult<Option<String>>;
+	async fn set_error_channel(&self, guild_id: &str, error_channel: Option<&str>) -> StorageResult<bool>;
+	/// Returns the defaults for guilds that never changed them
+	async fn get_output_settings(&self, guild_id: &str) -> StorageResult<OutputSettings>;
+	async fn set_output_settings(&self, guild_id: &str, settings: &OutputSettings) -> StorageResult<bool>;
+
+	/// Script databases, used by [crate::databases::SqlDatabase]
+	/// Keys past their expiry time are filtered out of every read, so they never show up in any of these
+	/// Writes treat them as if they didn't exist, actually deleting them is left to [sweep_expired_keys]
+	async fn get_key(&self, guild_id: &str, database: &str, key: &str) -> StorageResult<Option<StoredValue>>;
+	/// Writes the key, replacing its value and expiry time
+	/// `expires_at` is a unix timestamp in seconds, [None] means that the key never expires
+	/// The writes taking up space check `limits` in the same transaction as the write, so concurrent scripts can't overshoot them together
+	async fn write_key(&self, guild_id: &str, database: &str, key: &str, value: &StoredValue, expires_at: Option<i64>, limits: &QuotaLimits) -> Result<(), QuotaError>;
+	/// Changes when an existing key expires, returns `false` if the key doesn't exist
+	async fn set_key_expiry(&self, guild_id: &str, database: &str, key: &str, expires_at: Option<i64>) -> StorageResult<bool>;
+	async fn remove_key(&self, guild_id: &str, database: &str, key: &str) -> StorageResult<()>;
+	async fn key_exists(&self, guild_id: &str, database: &str, key: &str) -> StorageResult<bool>;
+	/// Adds `amount` to the integer value of the key in a single statement and returns the result
+	/// Keys that don't exist start from 0, keys holding any other type are left alone and [None] is returned
+	async fn increment_key(&self, guild_id: &str, database: &str, key: &str, amount: i64, limits: &QuotaLimits) -> Result<Option<i64>, QuotaError>;
+	/// Writes `new` only if the key currently holds `expected`, both the value and its type, with [None] meaning that the key must not exist
+	/// Returns `true` if the value was written
+	async fn compare_and_swap(&self, guild_id: &str, database: &str, key: &str, expected: Option<&StoredValue>, new: &StoredValue, limits: &QuotaLimits) -> Result<bool, QuotaError>;
+	/// Returns up to `limit` key names sorted alphabetically, skipping the first `offset` of them
+	async fn list_keys(&self, guild_id: &str, database: &str, offset: i64, limit: i64) -> StorageResult<Vec<String>>;
+	/// Returns every key name starting with `prefix`, sorted alphabetically
+	async fn keys_with_prefix(&self, guild_id: &str, database: &str, prefix: &str) -> StorageResult<Vec<String>>;
+	async fn count_keys(&self, guild_id: &str, database: &str) -> StorageResult<i64>;
+	/// Returns up to `amount` `(key, value)` pairs holding integers or floats, sorted by the value
+	async fn top_keys(&self, guild_id: &str, database: &str, amount: i64, descending: bool) -> StorageResult<Vec<(String, StoredValue)>>;
+	/// Returns every `(key, value)` pair in the database, sorted by key
+	async fn dump_database(&self, guild_id: &str, database: &str) -> StorageResult<Vec<(String, StoredValue)>>;
+	/// Databases are registered when a key is first written to them and stay registered until dropped
+	async fn database_exists(&self, guild_id: &str, database: &str) -> StorageResult<bool>;
+	/// Returns every registered database in the guild, sorted by name
+	async fn list_databases(&self, guild_id: &str) -> StorageResult<Vec<DatabaseInfo>>;
+	/// Returns `false` if the database isn't registered
+	async fn set_database_description(&self, guild_id: &str, database: &str, description: Option<&str>) -> StorageResult<bool>;
+	/// Removes every key in the database, the database itself stays registered
+	async fn clear_database(&self, guild_id: &str, database: &str) -> StorageResult<()>;
+	/// Removes every key in the database and the database itself
+	async fn drop_database(&self, guild_id: &str, database: &str) -> StorageResult<()>;
+	/// Removes every key that expired at or before `now` in all guilds, returns how many were removed
+	async fn purge_expired_keys(&self, now: i64) -> StorageResult<u64>;
+	/// How much the guild stores across all of its script databases, expired keys aren't counted
+	async fn usage(&self, guild_id: &str) -> StorageResult<Usage>;
+
+	/// Queues a message to be deleted at `delete_at`, a unix timestamp in seconds
+	async fn schedule_deletion(&self, channel_id: &str, message_id: &str, delete_at: i64) -> StorageResult<()>;
+	/// Returns every queued deletion due at or before `now`
+	async fn due_deletions(&self, now: i64) -> StorageResult<Vec<ScheduledDeletion>>;
+	/// When the earliest queued deletion is due, [None] if the queue is empty
+	async fn next_deletion_time(&self) -> StorageResult<Option<i64>>;
+	async fn remove_deletion(&self, id: i64) -> StorageResult<()>;
+
+	/// Stores the code run when the button or select menu with `custom_id` is used, replacing the previous code
+	/// Handlers and modals count towards `limits.max_handlers` together, checked in the same transaction as the write
+	async fn set_component_handler(&self, guild_id: &str, custom_id: &str, code: &str, limits: &QuotaLimits) -> Result<(), QuotaError>;
+	/// Finds the code for a used component, stored under the whole custom ID or else under the part before the first `:`
+	/// This lets scripts put data after a shared ID, like `vote` in `vote:yes` and `vote:no`
+	async fn find_component_handler(&self, guild_id: &str, custom_id: &str) -> StorageResult<Option<String>>;
+
+	/// Adds a guild's own slash command, replacing it if it already exists
+	async fn set_slash_command(&self, guild_id: &str, command: &CustomSlashCommand) -> StorageResult<()>;
+	/// Returns `false` if the command didn't exist
+	async fn remove_slash_command(&self, guild_id: &str, name: &str) -> StorageResult<bool>;
+	async fn get_slash_command(&self, guild_id: &str, name: &str) -> StorageResult<Option<CustomSlashCommand>>;
+	async fn list_slash_commands(&self, guild_id: &str) -> StorageResult<Vec<CustomSlashCommand>>;
+
+	/// Stores a modal defined by a script, replacing the previous one with the same ID
+	async fn set_modal(&self, guild_id: &str, modal: &StoredModal, limits: &QuotaLimits) -> Result<(), QuotaError>;
+	async fn get_modal(&self, guild_id: &str, modal_id: &str) -> StorageResult<Option<StoredModal>>;
+	/// Removes the component handlers and modals last stored before `stored_before` in all guilds, returns how many were removed
+	async fn purge_old_handlers(&self, stored_before: i64) -> StorageResult<u64>;
+}
+
+/// A message waiting to be deleted, see [crate::deletions]
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ScheduledDeletion {
+	pub id: i64,
+	pub channel_id: String,
+	pub message_id: String,
+}
+
+/// How script output is sent in a guild
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::FromRow)]
+pub struct OutputSettings {
+	/// Whether `@everyone` and `@here` ping
+	pub allow_everyone: bool,
+	pub allow_roles: bool,
+	pub allow_users: bool,
+	/// Whether replies ping the author of the message they reply to
+	pub ping_on_reply: bool,
+	/// Whether output going to the channel of the triggering message is sent as a reply to it
+	pub reply_to_trigger: bool,
+}
+
+impl Default for OutputSettings {
+	fn default() -> Self {
+		return Self {
+			allow_everyone: false,
+			allow_roles: false,
+			allow_users: true,
+			ping_on_reply: false,
+			reply_to_trigger: false,
+		};
+	}
+}
+
+/// What a script database value holds, stored next to it in `value_type`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+	String,
+	Integer,
+	Float,
+	Bool,
+	/// JSON array
+	Array,
+	/// JSON object
+	Map,
+}
+
+impl ValueType {
+	/// The name stored in the database
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::String => {
+				return "string";
+			}
+			Self::Integer => {
+				return "integer";
+			}
+			Self::Float => {
+				return "float";
+			}
+			Self::Bool => {
+				return "bool";
+			}
+			Self::Array => {
+				return "array";
+			}
+			Self::Map => {
+				return "map";
+			}
+		}
+	}
+
+	/// Unknown names are read as strings, so that a value is never lost
+	pub fn from_name(name: &str) -> Self {
+		match name {
+			"integer" => {
+				return Self::Integer;
+			}
+			"float" => {
+				return Self::Float;
+			}
+			"bool" => {
+				return Self::Bool;
+			}
+			"array" => {
+				return Self::Array;
+			}
+			"map" => {
+				return Self::Map;
+			}
+			_ => {
+				return Self::String;
+			}
+		}
+	}
+}
+
+/// A script database value as it is stored
+/// `text` is the value itself for strings, the decimal form for numbers, `true` or `false` for bools and JSON for arrays and maps
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredValue {
+	pub value_type: ValueType,
+	pub text: String,
+}
+
+impl StoredValue {
+	pub fn new(value_type: ValueType, text: String) -> Self {
+		return Self { value_type, text };
+	}
+
+	/// Builds the value from the `key_value` and `value_type` columns
+	fn from_row(text: String, value_type: String) -> Self {
+		return Self::new(ValueType::from_name(&value_type), text);
+	}
+
+	/// The value stored in `number_value`, which numeric sorting and aggregation use
+	pub fn number(&self) -> Option<f64> {
+		match self.value_type {
+			ValueType::Integer | ValueType::Float => {
+				return self.text.parse().ok();
+			}
+			_ => {
+				return None;
+			}
+		}
+	}
+
+	/// Converts the value to JSON, keeping its type
+	pub fn to_json(&self) -> serde_json::Value {
+		match self.value_type {
+			ValueType::String => {
+				return serde_json::Value::String(self.text.clone());
+			}
+			ValueType::Bool => {
+				return serde_json::Value::Bool(self.text == "true");
+			}
+			ValueType::Integer | ValueType::Float | ValueType::Array | ValueType::Map => {
+				return serde_json::from_str(&self.text).unwrap_or_else(|_| serde_json::Value::String(self.text.clone()));
+			}
+		}
+	}
+}
+
+/// A registered script database
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DatabaseInfo {
+	pub name: String,
+	/// Unix timestamp in seconds
+	pub created_at: i64,
+	pub description: Option<String>,
+	/// Keys that haven't expired
+	pub keys: i64,
+}
+
+/// A slash command defined by a guild, see [crate::custom_commands]
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CustomSlashCommand {
+	pub name: String,
+	pub description: String,
+	/// JSON list of [crate::custom_commands::SlashOption]
+	pub options: String,
+	pub code: String,
+}
+
+/// A modal defined by a script, opened by the button with the same custom ID
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredModal {
+	pub modal_id: String,
+	pub title: String,
+	/// JSON list of [crate::components::ModalInput]
+	pub inputs: String,
+	/// Runs when the modal is submitted
+	pub code: String,
+}
+
+/// Script database usage of a guild, checked against [crate::config::QuotaLimits]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+	pub keys: i64,
+	/// Sum of the sizes of key names and values in bytes
+	pub bytes: i64,
+	pub databases: i64,
+}
+
+/// Why a script database write was refused, shown to the script that attempted it
+#[derive(Debug)]
+pub enum QuotaError {
+	ValueTooLarge {
+		size: u64,
+		limit: u64,
+	},
+	TooManyKeys(u64),
+	TooManyBytes(u64),
+	TooManyDatabases(u64),
+	TooManyHandlers(u64),
+	Storage(sqlx::Error),
+}
+
+impl std::fmt::Display for QuotaError {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::ValueTooLarge { size, limit } => {
+				return write!(formatter, "value is {} bytes long, the limit is {} bytes", size, limit);
+			}
+			Self::TooManyKeys(limit) => {
+				return write!(formatter, "this server has reached its limit of {} keys", limit);
+			}
+			Self::TooManyBytes(limit) => {
+				return write!(formatter, "this server has reached its limit of {} bytes of stored data", limit);
+			}
+			Self::TooManyDatabases(limit) => {
+				return write!(formatter, "this server has reached its limit of {} databases", limit);
+			}
+			Self::TooManyHandlers(limit) => {
+				return write!(formatter, "this server has reached its limit of {} stored component handlers and modals", limit);
+			}
+			Self::Storage(error) => {
+				return write!(formatter, "database error: {}", error);
+			}
+		}
+	}
+}
+
+impl From<sqlx::Error> for QuotaError {
+	fn from(error: sqlx::Error) -> Self {
+		return Self::Storage(error);
+	}
+}
+
+/// Checks the usage of a guild after a write, which the backends roll back if this fails
+pub fn check_quota(usage: &Usage, limits: &QuotaLimits) -> Result<(), QuotaError> {
+	if usage.keys as u64 > limits.max_keys {
+		return Err(QuotaError::TooManyKeys(limits.max_keys));
+	}
+	if usage.bytes as u64 > limits.max_total_bytes {
+		return Err(QuotaError::TooManyBytes(limits.max_total_bytes));
+	}
+	if usage.databases as u64 > limits.max_databases {
+		return Err(QuotaError::TooManyDatabases(limits.max_databases));
+	}
+	return Ok(());
+}
+
+/// Checks how many component handlers and modals a guild has after storing one, which the backends roll back if this fails
+pub fn check_handlers(count: i64, limits: &QuotaLimits) -> Result<(), QuotaError> {
+	if count as u64 > limits.max_handlers {
+		return Err(QuotaError::TooManyHandlers(limits.max_handlers));
+	}
+	return Ok(());
+}
+
+/// Checked before the write, a value over the limit is refused whatever the rest of the guild stores
+pub fn check_value_size(value: &StoredValue, limits: &QuotaLimits) -> Result<(), QuotaError> {
+	let size = value.text.len() as u64;
+	if size > limits.max_value_size {
+		return Err(QuotaError::ValueTooLarge { size, limit: limits.max_value_size });
+	}
+	return Ok(());
+}
+
+/// Returns `true` if there is a [Storage] implementation for the URL
+pub fn is_supported_url(database_url: &str) -> bool {
+	return database_url.starts_with("sqlite:") || is_postgres_url(database_url);
+}
+
+/// Connects to the database pointed to by the URL
+pub async fn connect(database_url: &str) -> StorageResult<Arc<dyn Storage>> {
+	if is_postgres_url(database_url) {
+		return Ok(Arc::new(PostgresStorage::connect(database_url).await?));
+	} else {
+		return Ok(Arc::new(SqliteStorage::connect(database_url).await?));
+	}
+}
+
+/// SQL condition matching the values that count as numbers, the same types [StoredValue::number] gives a number for
+/// Both backends use it, so that `top_keys` picks the same keys whichever one is used
+pub const NUMERIC_VALUE: &str = "value_type IN ('integer', 'float')";
+
+/// The part of a component's custom ID before the first `:`, which a handler can be stored under to handle every ID sharing it
+pub fn shared_custom_id(custom_id: &str) -> &str {
+	return custom_id.split(':').next().unwrap_or(custom_id);
+}
+
+/// Current unix timestamp in seconds, as used for key expiry
+pub fn unix_now() -> i64 {
+	return std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+}
+
+/// Removes expired script database keys every `interval`, along with component handlers and modals older than `handler_lifetime`
+/// Reads and writes already skip expired keys, this is the only place that actually deletes them
+pub async fn sweep_expired_keys(storage: Arc<dyn Storage>, interval: std::time::Duration, handler_lifetime: Option<std::time::Duration>) {
+	let mut timer = tokio::time::interval(interval);
+	loop {
+		timer.tick().await;
+		if let Err(error) = storage.purge_expired_keys(unix_now()).await {
+			tracing::error!("sweep_expired_keys: DB error: `{}`", error);
+		}
+		if let Some(lifetime) = handler_lifetime {
+			if let Err(error) = storage.purge_old_handlers(unix_now() - lifetime.as_secs() as i64).await {
+				tracing::error!("sweep_expired_keys: DB error: `{}`", error);
+			}
+		}
+	}
+}
+
+fn is_postgres_url(database_url: &str) -> bool {
+	return database_url.starts_with("postgres:") || database_url.starts_with("postgresql:");
+}
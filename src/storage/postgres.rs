@@ -0,0 +1,367 @@
+use serenity::async_trait;
+use sqlx::{
+	migrate::Migrator,
+	postgres::{
+		PgConnection,
+		PgPool,
+	},
+};
+use crate::config::QuotaLimits;
+use crate::migrations::{self, MigrationError};
+use super::{CustomSlashCommand, DatabaseInfo, OutputSettings, QuotaError, ScheduledDeletion, Storage, StorageResult, StoredModal, StoredValue, Usage};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+pub struct PostgresStorage {
+	pool: PgPool,
+}
+
+impl PostgresStorage {
+	/// Connects to the server, the database itself has to already exist
+	pub async fn connect(database_url: &str) -> StorageResult<Self> {
+		let pool = PgPool::connect(database_url).await?;
+		return Ok(Self { pool });
+	}
+
+	/// Registers the database if this is the first time a key is written to it
+	async fn register_database(connection: &mut PgConnection, guild_id: &str, database: &str) -> StorageResult<()> {
+		let query = sqlx::query("INSERT INTO database_registry (guild_id, name, created_at) VALUES ($1, $2, $3) ON CONFLICT (guild_id, name) DO NOTHING").bind(guild_id).bind(database).bind(super::unix_now());
+		query.execute(connection).await?;
+		return Ok(());
+	}
+
+	/// Takes a connection, so that the quota checks can see the uncommitted write of their transaction
+	async fn guild_usage(connection: &mut PgConnection, guild_id: &str) -> StorageResult<Usage> {
+		let query = sqlx::query_as::<_, (i64, i64, i64)>("SELECT COUNT(*), COALESCE(SUM(OCTET_LENGTH(key_name) + OCTET_LENGTH(key_value)), 0)::BIGINT, (SELECT COUNT(*) FROM database_registry WHERE database_registry.guild_id = $1) FROM databases WHERE guild_id = $1 AND (expires_at IS NULL OR expires_at > $2)").bind(guild_id).bind(super::unix_now());
+		let (keys, bytes, databases) = query.fetch_one(connection).await?;
+		return Ok(Usage { keys, bytes, databases });
+	}
+
+	/// Holds back other quota checked writes of the guild until the transaction ends
+	/// Postgres has no rows to lock for keys that don't exist yet, without this two new keys written at once would both pass the check
+	async fn lock_guild(connection: &mut PgConnection, guild_id: &str) -> StorageResult<()> {
+		let query = sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))").bind(guild_id);
+		query.execute(connection).await?;
+		return Ok(());
+	}
+
+	/// Component handlers and modals the guild has stored, counted together against `max_handlers`
+	async fn count_handlers(connection: &mut PgConnection, guild_id: &str) -> StorageResult<i64> {
+		let query = sqlx::query_scalar::<_, i64>("SELECT (SELECT COUNT(*) FROM component_handlers WHERE guild_id = $1) + (SELECT COUNT(*) FROM modals WHERE guild_id = $1)").bind(guild_id);
+		return query.fetch_one(connection).await;
+	}
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+	async fn migrate(&self) -> Result<(), MigrationError> {
+		return migrations::run(&MIGRATOR, &self.pool).await;
+	}
+
+	fn schema_version(&self) -> i64 {
+		return migrations::latest_version(&MIGRATOR);
+	}
+
+	async fn get_triggers(&self, guild_id: &str) -> StorageResult<Vec<(String, String)>> {
+		let query = sqlx::query_as::<_, (String, String)>("SELECT trigger, code FROM triggers WHERE guild_id = $1").bind(guild_id);
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn get_trigger(&self, guild_id: &str, trigger: &str) -> StorageResult<Option<String>> {
+		let query = sqlx::query_scalar::<_, String>("SELECT code FROM triggers WHERE trigger = $1 AND guild_id = $2").bind(trigger).bind(guild_id);
+		return query.fetch_optional(&self.pool).await;
+	}
+
+	async fn set_trigger(&self, guild_id: &str, trigger: &str, code: &str) -> StorageResult<()> {
+		let query = sqlx::query("INSERT INTO triggers VALUES ($1, $2, $3) ON CONFLICT (trigger, guild_id) DO UPDATE SET code = excluded.code").bind(trigger).bind(code).bind(guild_id);
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn remove_trigger(&self, guild_id: &str, trigger: &str) -> StorageResult<bool> {
+		let query = sqlx::query("DELETE FROM triggers WHERE trigger = $1 AND guild_id = $2").bind(trigger).bind(guild_id);
+		return Ok(query.execute(&self.pool).await?.rows_affected() > 0);
+	}
+
+	async fn list_events(&self, guild_id: &str) -> StorageResult<Vec<String>> {
+		let query = sqlx::query_scalar::<_, String>("SELECT event FROM events WHERE guild_id = $1 ORDER BY event").bind(guild_id);
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn get_event(&self, guild_id: &str, event: &str) -> StorageResult<Option<String>> {
+		let query = sqlx::query_scalar::<_, String>("SELECT code FROM events WHERE event = $1 AND guild_id = $2").bind(event).bind(guild_id);
+		return query.fetch_optional(&self.pool).await;
+	}
+
+	async fn set_event(&self, guild_id: &str, event: &str, code: &str) -> StorageResult<()> {
+		let query = sqlx::query("INSERT INTO events VALUES ($1, $2, $3) ON CONFLICT (guild_id, event) DO UPDATE SET code = excluded.code").bind(event).bind(guild_id).bind(code);
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn remove_event(&self, guild_id: &str, event: &str) -> StorageResult<bool> {
+		let query = sqlx::query("DELETE FROM events WHERE event = $1 AND guild_id = $2").bind(event).bind(guild_id);
+		return Ok(query.execute(&self.pool).await?.rows_affected() > 0);
+	}
+
+	async fn get_prefix(&self, guild_id: &str) -> StorageResult<Option<String>> {
+		let query = sqlx::query_scalar::<_, Option<String>>("SELECT prefix FROM config WHERE guild_id = $1").bind(guild_id);
+		return Ok(query.fetch_optional(&self.pool).await?.flatten());
+	}
+
+	async fn set_prefix(&self, guild_id: &str, prefix: &str) -> StorageResult<bool> {
+		let query = sqlx::query("INSERT INTO config (guild_id, prefix) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET prefix = excluded.prefix").bind(guild_id).bind(prefix);
+		return Ok(query.execute(&self.pool).await?.rows_affected() == 1);
+	}
+
+	async fn get_admin_role(&self, guild_id: &str) -> StorageResult<Option<String>> {
+		let query = sqlx::query_scalar::<_, Option<String>>("SELECT admin_role FROM config WHERE guild_id = $1").bind(guild_id);
+		return Ok(query.fetch_optional(&self.pool).await?.flatten());
+	}
+
+	async fn set_admin_role(&self, guild_id: &str, admin_role: Option<&str>) -> StorageResult<bool> {
+		let query = sqlx::query("INSERT INTO config (guild_id, admin_role) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET admin_role = excluded.admin_role").bind(guild_id).bind(admin_role);
+		return Ok(query.execute(&self.pool).await?.rows_affected() == 1);
+	}
+
+	async fn get_error_channel(&self, guild_id: &str) -> StorageResult<Option<String>> {
+		let query = sqlx::query_scalar::<_, Option<String>>("SELECT error_channel FROM config WHERE guild_id = $1").bind(guild_id);
+		return Ok(query.fetch_optional(&self.pool).await?.flatten());
+	}
+
+	async fn set_error_channel(&self, guild_id: &str, error_channel: Option<&str>) -> StorageResult<bool> {
+		let query = sqlx::query("INSERT INTO config (guild_id, error_channel) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET error_channel = excluded.error_channel").bind(guild_id).bind(error_channel);
+		return Ok(query.execute(&self.pool).await?.rows_affected() == 1);
+	}
+
+	async fn get_output_settings(&self, guild_id: &str) -> StorageResult<OutputSettings> {
+		let query = sqlx::query_as::<_, OutputSettings>("SELECT allow_everyone, allow_roles, allow_users, ping_on_reply, reply_to_trigger FROM config WHERE guild_id = $1").bind(guild_id);
+		return Ok(query.fetch_optional(&self.pool).await?.unwrap_or_default());
+	}
+
+	async fn set_output_settings(&self, guild_id: &str, settings: &OutputSettings) -> StorageResult<bool> {
+		let query = sqlx::query("INSERT INTO config (guild_id, allow_everyone, allow_roles, allow_users, ping_on_reply, reply_to_trigger) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (guild_id) DO UPDATE SET allow_everyone = excluded.allow_everyone, allow_roles = excluded.allow_roles, allow_users = excluded.allow_users, ping_on_reply = excluded.ping_on_reply, reply_to_trigger = excluded.reply_to_trigger").bind(guild_id).bind(settings.allow_everyone).bind(settings.allow_roles).bind(settings.allow_users).bind(settings.ping_on_reply).bind(settings.reply_to_trigger);
+		return Ok(query.execute(&self.pool).await?.rows_affected() == 1);
+	}
+
+	async fn get_key(&self, guild_id: &str, database: &str, key: &str) -> StorageResult<Option<StoredValue>> {
+		let query = sqlx::query_as::<_, (String, String)>("SELECT key_value, value_type FROM databases WHERE name = $1 AND guild_id = $2 AND key_name = $3 AND (expires_at IS NULL OR expires_at > $4)").bind(database).bind(guild_id).bind(key).bind(super::unix_now());
+		return Ok(query.fetch_optional(&self.pool).await?.map(|(text, value_type)| StoredValue::from_row(text, value_type)));
+	}
+
+	async fn write_key(&self, guild_id: &str, database: &str, key: &str, value: &StoredValue, expires_at: Option<i64>, limits: &QuotaLimits) -> Result<(), QuotaError> {
+		super::check_value_size(value, limits)?;
+		let mut transaction = self.pool.begin().await?;
+		//Taking the guild's lock first makes every quota checked write of the guild wait for the previous one to commit
+		Self::lock_guild(&mut transaction, guild_id).await?;
+		Self::register_database(&mut transaction, guild_id, database).await?;
+		let query = sqlx::query("INSERT INTO databases (name, guild_id, key_name, key_value, value_type, number_value, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (name, guild_id, key_name) DO UPDATE SET key_value = excluded.key_value, value_type = excluded.value_type, number_value = excluded.number_value, expires_at = excluded.expires_at").bind(database).bind(guild_id).bind(key).bind(&value.text).bind(value.value_type.as_str()).bind(value.number()).bind(expires_at);
+		query.execute(&mut transaction).await?;
+		super::check_quota(&Self::guild_usage(&mut transaction, guild_id).await?, limits)?;
+		transaction.commit().await?;
+		return Ok(());
+	}
+
+	async fn set_key_expiry(&self, guild_id: &str, database: &str, key: &str, expires_at: Option<i64>) -> StorageResult<bool> {
+		let query = sqlx::query("UPDATE databases SET expires_at = $1 WHERE name = $2 AND guild_id = $3 AND key_name = $4 AND (expires_at IS NULL OR expires_at > $5)").bind(expires_at).bind(database).bind(guild_id).bind(key).bind(super::unix_now());
+		return Ok(query.execute(&self.pool).await?.rows_affected() == 1);
+	}
+
+	async fn remove_key(&self, guild_id: &str, database: &str, key: &str) -> StorageResult<()> {
+		let query = sqlx::query("DELETE FROM databases WHERE name = $1 AND guild_id = $2 AND key_name = $3").bind(database).bind(guild_id).bind(key);
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn key_exists(&self, guild_id: &str, database: &str, key: &str) -> StorageResult<bool> {
+		let query = sqlx::query("SELECT name FROM databases WHERE name = $1 AND guild_id = $2 AND key_name = $3 AND (expires_at IS NULL OR expires_at > $4)").bind(database).bind(guild_id).bind(key).bind(super::unix_now());
+		return Ok(query.fetch_optional(&self.pool).await?.is_some());
+	}
+
+	async fn increment_key(&self, guild_id: &str, database: &str, key: &str, amount: i64, limits: &QuotaLimits) -> Result<Option<i64>, QuotaError> {
+		let mut transaction = self.pool.begin().await?;
+		Self::lock_guild(&mut transaction, guild_id).await?;
+		Self::register_database(&mut transaction, guild_id, database).await?;
+		let query = sqlx::query_scalar::<_, i64>("INSERT INTO databases (name, guild_id, key_name, key_value, value_type, number_value) VALUES ($1, $2, $3, $4, 'integer', $5) ON CONFLICT (name, guild_id, key_name) DO UPDATE SET key_value = CAST(CASE WHEN databases.expires_at IS NULL OR databases.expires_at > $7 THEN CAST(databases.key_value AS BIGINT) ELSE 0 END + $6 AS TEXT), number_value = CAST(CASE WHEN databases.expires_at IS NULL OR databases.expires_at > $7 THEN CAST(databases.key_value AS BIGINT) ELSE 0 END + $6 AS DOUBLE PRECISION), value_type = 'integer', expires_at = CASE WHEN databases.expires_at > $7 THEN databases.expires_at END WHERE databases.value_type = 'integer' OR databases.expires_at <= $7 RETURNING CAST(key_value AS BIGINT)").bind(database).bind(guild_id).bind(key).bind(amount.to_string()).bind(amount as f64).bind(amount).bind(super::unix_now());
+		let result = query.fetch_optional(&mut transaction).await?;
+		if result.is_none() {
+			//Nothing was written, dropping the transaction rolls back registering the database
+			return Ok(None);
+		}
+		super::check_quota(&Self::guild_usage(&mut transaction, guild_id).await?, limits)?;
+		transaction.commit().await?;
+		return Ok(result);
+	}
+
+	async fn compare_and_swap(&self, guild_id: &str, database: &str, key: &str, expected: Option<&StoredValue>, new: &StoredValue, limits: &QuotaLimits) -> Result<bool, QuotaError> {
+		super::check_value_size(new, limits)?;
+		let mut transaction = self.pool.begin().await?;
+		Self::lock_guild(&mut transaction, guild_id).await?;
+		let query;
+		match expected {
+			Some(expected) => {
+				query = sqlx::query("UPDATE databases SET key_value = $1, value_type = $2, number_value = $3 WHERE name = $4 AND guild_id = $5 AND key_name = $6 AND key_value = $7 AND value_type = $8 AND (expires_at IS NULL OR expires_at > $9)").bind(&new.text).bind(new.value_type.as_str()).bind(new.number()).bind(database).bind(guild_id).bind(key).bind(&expected.text).bind(expected.value_type.as_str()).bind(super::unix_now());
+			}
+			None => {
+				Self::register_database(&mut transaction, guild_id, database).await?;
+				query = sqlx::query("INSERT INTO databases (name, guild_id, key_name, key_value, value_type, number_value) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (name, guild_id, key_name) DO UPDATE SET key_value = excluded.key_value, value_type = excluded.value_type, number_value = excluded.number_value, expires_at = NULL WHERE databases.expires_at <= $7").bind(database).bind(guild_id).bind(key).bind(&new.text).bind(new.value_type.as_str()).bind(new.number()).bind(super::unix_now());
+			}
+		}
+		if query.execute(&mut transaction).await?.rows_affected() != 1 {
+			return Ok(false);
+		}
+		super::check_quota(&Self::guild_usage(&mut transaction, guild_id).await?, limits)?;
+		transaction.commit().await?;
+		return Ok(true);
+	}
+
+	async fn list_keys(&self, guild_id: &str, database: &str, offset: i64, limit: i64) -> StorageResult<Vec<String>> {
+		let query = sqlx::query_scalar::<_, String>("SELECT key_name FROM databases WHERE name = $1 AND guild_id = $2 AND (expires_at IS NULL OR expires_at > $3) ORDER BY key_name LIMIT $4 OFFSET $5").bind(database).bind(guild_id).bind(super::unix_now()).bind(limit).bind(offset);
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn keys_with_prefix(&self, guild_id: &str, database: &str, prefix: &str) -> StorageResult<Vec<String>> {
+		let query = sqlx::query_scalar::<_, String>("SELECT key_name FROM databases WHERE name = $1 AND guild_id = $2 AND substr(key_name, 1, length($3)) = $4 AND (expires_at IS NULL OR expires_at > $5) ORDER BY key_name").bind(database).bind(guild_id).bind(prefix).bind(prefix).bind(super::unix_now());
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn count_keys(&self, guild_id: &str, database: &str) -> StorageResult<i64> {
+		let query = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM databases WHERE name = $1 AND guild_id = $2 AND (expires_at IS NULL OR expires_at > $3)").bind(database).bind(guild_id).bind(super::unix_now());
+		return query.fetch_one(&self.pool).await;
+	}
+
+	async fn top_keys(&self, guild_id: &str, database: &str, amount: i64, descending: bool) -> StorageResult<Vec<(String, StoredValue)>> {
+		let order;
+		if descending {
+			order = "DESC";
+		} else {
+			order = "ASC";
+		}
+		let sql = format!("SELECT key_name, key_value, value_type FROM databases WHERE name = $1 AND guild_id = $2 AND {} AND (expires_at IS NULL OR expires_at > $3) ORDER BY number_value {}, key_name LIMIT $4", super::NUMERIC_VALUE, order);
+		let query = sqlx::query_as::<_, (String, String, String)>(&sql).bind(database).bind(guild_id).bind(super::unix_now()).bind(amount);
+		return Ok(query.fetch_all(&self.pool).await?.into_iter().map(|(key, text, value_type)| (key, StoredValue::from_row(text, value_type))).collect());
+	}
+
+	async fn dump_database(&self, guild_id: &str, database: &str) -> StorageResult<Vec<(String, StoredValue)>> {
+		let query = sqlx::query_as::<_, (String, String, String)>("SELECT key_name, key_value, value_type FROM databases WHERE name = $1 AND guild_id = $2 AND (expires_at IS NULL OR expires_at > $3) ORDER BY key_name").bind(database).bind(guild_id).bind(super::unix_now());
+		return Ok(query.fetch_all(&self.pool).await?.into_iter().map(|(key, text, value_type)| (key, StoredValue::from_row(text, value_type))).collect());
+	}
+
+	async fn database_exists(&self, guild_id: &str, database: &str) -> StorageResult<bool> {
+		let query = sqlx::query("SELECT name FROM database_registry WHERE guild_id = $1 AND name = $2").bind(guild_id).bind(database);
+		return Ok(query.fetch_optional(&self.pool).await?.is_some());
+	}
+
+	async fn list_databases(&self, guild_id: &str) -> StorageResult<Vec<DatabaseInfo>> {
+		let query = sqlx::query_as::<_, DatabaseInfo>("SELECT database_registry.name, database_registry.created_at, database_registry.description, COUNT(databases.key_name) AS keys FROM database_registry LEFT JOIN databases ON databases.guild_id = database_registry.guild_id AND databases.name = database_registry.name AND (databases.expires_at IS NULL OR databases.expires_at > $1) WHERE database_registry.guild_id = $2 GROUP BY database_registry.name, database_registry.created_at, database_registry.description ORDER BY database_registry.name").bind(super::unix_now()).bind(guild_id);
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn set_database_description(&self, guild_id: &str, database: &str, description: Option<&str>) -> StorageResult<bool> {
+		let query = sqlx::query("UPDATE database_registry SET description = $1 WHERE guild_id = $2 AND name = $3").bind(description).bind(guild_id).bind(database);
+		return Ok(query.execute(&self.pool).await?.rows_affected() == 1);
+	}
+
+	async fn clear_database(&self, guild_id: &str, database: &str) -> StorageResult<()> {
+		let query = sqlx::query("DELETE FROM databases WHERE name = $1 AND guild_id = $2").bind(database).bind(guild_id);
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn drop_database(&self, guild_id: &str, database: &str) -> StorageResult<()> {
+		let mut transaction = self.pool.begin().await?;
+		sqlx::query("DELETE FROM databases WHERE name = $1 AND guild_id = $2").bind(database).bind(guild_id).execute(&mut transaction).await?;
+		sqlx::query("DELETE FROM database_registry WHERE guild_id = $1 AND name = $2").bind(guild_id).bind(database).execute(&mut transaction).await?;
+		return transaction.commit().await;
+	}
+
+	async fn purge_expired_keys(&self, now: i64) -> StorageResult<u64> {
+		let query = sqlx::query("DELETE FROM databases WHERE expires_at <= $1").bind(now);
+		return Ok(query.execute(&self.pool).await?.rows_affected());
+	}
+
+	async fn usage(&self, guild_id: &str) -> StorageResult<Usage> {
+		return Self::guild_usage(&mut *self.pool.acquire().await?, guild_id).await;
+	}
+
+	async fn schedule_deletion(&self, channel_id: &str, message_id: &str, delete_at: i64) -> StorageResult<()> {
+		let query = sqlx::query("INSERT INTO scheduled_deletions (channel_id, message_id, delete_at) VALUES ($1, $2, $3)").bind(channel_id).bind(message_id).bind(delete_at);
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn due_deletions(&self, now: i64) -> StorageResult<Vec<ScheduledDeletion>> {
+		let query = sqlx::query_as::<_, ScheduledDeletion>("SELECT id, channel_id, message_id FROM scheduled_deletions WHERE delete_at <= $1 ORDER BY delete_at").bind(now);
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn next_deletion_time(&self) -> StorageResult<Option<i64>> {
+		let query = sqlx::query_scalar::<_, Option<i64>>("SELECT MIN(delete_at) FROM scheduled_deletions");
+		return query.fetch_one(&self.pool).await;
+	}
+
+	async fn remove_deletion(&self, id: i64) -> StorageResult<()> {
+		let query = sqlx::query("DELETE FROM scheduled_deletions WHERE id = $1").bind(id);
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn set_component_handler(&self, guild_id: &str, custom_id: &str, code: &str, limits: &QuotaLimits) -> Result<(), QuotaError> {
+		let mut transaction = self.pool.begin().await?;
+		Self::lock_guild(&mut transaction, guild_id).await?;
+		let query = sqlx::query("INSERT INTO component_handlers (guild_id, custom_id, code, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (guild_id, custom_id) DO UPDATE SET code = excluded.code, created_at = excluded.created_at").bind(guild_id).bind(custom_id).bind(code).bind(super::unix_now());
+		query.execute(&mut transaction).await?;
+		super::check_handlers(Self::count_handlers(&mut transaction, guild_id).await?, limits)?;
+		transaction.commit().await?;
+		return Ok(());
+	}
+
+	async fn find_component_handler(&self, guild_id: &str, custom_id: &str) -> StorageResult<Option<String>> {
+		let query = sqlx::query_scalar::<_, String>("SELECT code FROM component_handlers WHERE guild_id = $1 AND custom_id IN ($2, $3) ORDER BY length(custom_id) DESC LIMIT 1").bind(guild_id).bind(custom_id).bind(super::shared_custom_id(custom_id));
+		return query.fetch_optional(&self.pool).await;
+	}
+
+	async fn set_slash_command(&self, guild_id: &str, command: &CustomSlashCommand) -> StorageResult<()> {
+		let query = sqlx::query("INSERT INTO custom_slash_commands (guild_id, name, description, options, code, created_at) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (guild_id, name) DO UPDATE SET description = excluded.description, options = excluded.options, code = excluded.code, created_at = excluded.created_at").bind(guild_id).bind(&command.name).bind(&command.description).bind(&command.options).bind(&command.code).bind(super::unix_now());
+		query.execute(&self.pool).await?;
+		return Ok(());
+	}
+
+	async fn remove_slash_command(&self, guild_id: &str, name: &str) -> StorageResult<bool> {
+		let query = sqlx::query("DELETE FROM custom_slash_commands WHERE guild_id = $1 AND name = $2").bind(guild_id).bind(name);
+		return Ok(query.execute(&self.pool).await?.rows_affected() > 0);
+	}
+
+	async fn get_slash_command(&self, guild_id: &str, name: &str) -> StorageResult<Option<CustomSlashCommand>> {
+		let query = sqlx::query_as::<_, CustomSlashCommand>("SELECT name, description, options, code FROM custom_slash_commands WHERE guild_id = $1 AND name = $2").bind(guild_id).bind(name);
+		return query.fetch_optional(&self.pool).await;
+	}
+
+	async fn list_slash_commands(&self, guild_id: &str) -> StorageResult<Vec<CustomSlashCommand>> {
+		let query = sqlx::query_as::<_, CustomSlashCommand>("SELECT name, description, options, code FROM custom_slash_commands WHERE guild_id = $1 ORDER BY name").bind(guild_id);
+		return query.fetch_all(&self.pool).await;
+	}
+
+	async fn set_modal(&self, guild_id: &str, modal: &StoredModal, limits: &QuotaLimits) -> Result<(), QuotaError> {
+		let mut transaction = self.pool.begin().await?;
+		Self::lock_guild(&mut transaction, guild_id).await?;
+		let query = sqlx::query("INSERT INTO modals (guild_id, modal_id, title, inputs, code, created_at) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (guild_id, modal_id) DO UPDATE SET title = excluded.title, inputs = excluded.inputs, code = excluded.code, created_at = excluded.created_at").bind(guild_id).bind(&modal.modal_id).bind(&modal.title).bind(&modal.inputs).bind(&modal.code).bind(super::unix_now());
+		query.execute(&mut transaction).await?;
+		super::check_handlers(Self::count_handlers(&mut transaction, guild_id).await?, limits)?;
+		transaction.commit().await?;
+		return Ok(());
+	}
+
+	async fn get_modal(&self, guild_id: &str, modal_id: &str) -> StorageResult<Option<StoredModal>> {
+		let query = sqlx::query_as::<_, StoredModal>("SELECT modal_id, title, inputs, code FROM modals WHERE guild_id = $1 AND modal_id = $2").bind(guild_id).bind(modal_id);
+		return query.fetch_optional(&self.pool).await;
+	}
+
+	async fn purge_old_handlers(&self, stored_before: i64) -> StorageResult<u64> {
+		let mut transaction = self.pool.begin().await?;
+		let handlers = sqlx::query("DELETE FROM component_handlers WHERE created_at < $1").bind(stored_before).execute(&mut transaction).await?.rows_affected();
+		let modals = sqlx::query("DELETE FROM modals WHERE created_at < $1").bind(stored_before).execute(&mut transaction).await?.rows_affected();
+		transaction.commit().await?;
+		return Ok(handlers + modals);
+	}
+}
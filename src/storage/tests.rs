@@ -0,0 +1,273 @@
+use std::sync::Arc;
+use crate::config::QuotaLimits;
+use super::*;
+
+const GUILD: &str = "1";
+const OTHER_GUILD: &str = "2";
+const LIMITS: QuotaLimits = QuotaLimits {
+	max_keys: 100,
+	max_total_bytes: 10_000,
+	max_value_size: 1_000,
+	max_databases: 5,
+	max_handlers: 10,
+};
+
+fn string(text: &str) -> StoredValue {
+	return StoredValue::new(ValueType::String, String::from(text));
+}
+
+fn integer(number: i64) -> StoredValue {
+	return StoredValue::new(ValueType::Integer, number.to_string());
+}
+
+/// Runs every [Storage] method against a freshly migrated database
+/// Both backends go through the same checks, so a query that only works on one of them fails here
+pub async fn exercise(storage: Arc<dyn Storage>) {
+	storage.migrate().await.unwrap();
+	triggers_and_events(&*storage).await;
+	guild_config(&*storage).await;
+	keys(&*storage).await;
+	registry(&*storage).await;
+	expiry(&*storage).await;
+	quotas(&*storage).await;
+	deletions(&*storage).await;
+	interactions(&*storage).await;
+}
+
+async fn triggers_and_events(storage: &dyn Storage) {
+	storage.set_trigger(GUILD, "hello", "code").await.unwrap();
+	storage.set_trigger(GUILD, "hello", "new code").await.unwrap();
+	assert_eq!(storage.get_trigger(GUILD, "hello").await.unwrap(), Some(String::from("new code")));
+	assert_eq!(storage.get_triggers(GUILD).await.unwrap(), vec![(String::from("hello"), String::from("new code"))]);
+	assert_eq!(storage.get_trigger(OTHER_GUILD, "hello").await.unwrap(), None);
+	assert!(storage.remove_trigger(GUILD, "hello").await.unwrap());
+	assert!(!storage.remove_trigger(GUILD, "hello").await.unwrap());
+
+	storage.set_event(GUILD, "MemberJoin", "code").await.unwrap();
+	storage.set_event(GUILD, "ChannelCreate", "code").await.unwrap();
+	assert_eq!(storage.list_events(GUILD).await.unwrap(), vec![String::from("ChannelCreate"), String::from("MemberJoin")]);
+	assert_eq!(storage.get_event(GUILD, "MemberJoin").await.unwrap(), Some(String::from("code")));
+	assert!(storage.remove_event(GUILD, "MemberJoin").await.unwrap());
+	assert_eq!(storage.get_event(GUILD, "MemberJoin").await.unwrap(), None);
+}
+
+async fn guild_config(storage: &dyn Storage) {
+	assert_eq!(storage.get_prefix(GUILD).await.unwrap(), None);
+	storage.set_prefix(GUILD, "!").await.unwrap();
+	storage.set_admin_role(GUILD, Some("10")).await.unwrap();
+	storage.set_error_channel(GUILD, Some("20")).await.unwrap();
+	assert_eq!(storage.get_prefix(GUILD).await.unwrap(), Some(String::from("!")));
+	assert_eq!(storage.get_admin_role(GUILD).await.unwrap(), Some(String::from("10")));
+	assert_eq!(storage.get_error_channel(GUILD).await.unwrap(), Some(String::from("20")));
+	storage.set_admin_role(GUILD, None).await.unwrap();
+	assert_eq!(storage.get_admin_role(GUILD).await.unwrap(), None);
+	assert_eq!(storage.get_prefix(GUILD).await.unwrap(), Some(String::from("!")));
+
+	assert_eq!(storage.get_output_settings(OTHER_GUILD).await.unwrap(), OutputSettings::default());
+	let settings = OutputSettings { allow_everyone: true, ..OutputSettings::default() };
+	storage.set_output_settings(GUILD, &settings).await.unwrap();
+	assert_eq!(storage.get_output_settings(GUILD).await.unwrap(), settings);
+}
+
+async fn keys(storage: &dyn Storage) {
+	storage.write_key(GUILD, "keys", "b", &string("text"), None, &LIMITS).await.unwrap();
+	storage.write_key(GUILD, "keys", "a", &integer(5), None, &LIMITS).await.unwrap();
+	storage.write_key(GUILD, "keys", "c", &StoredValue::new(ValueType::Float, String::from("1.5")), None, &LIMITS).await.unwrap();
+	assert_eq!(storage.get_key(GUILD, "keys", "a").await.unwrap(), Some(integer(5)));
+	assert_eq!(storage.get_key(OTHER_GUILD, "keys", "a").await.unwrap(), None);
+	assert!(storage.key_exists(GUILD, "keys", "b").await.unwrap());
+	assert_eq!(storage.count_keys(GUILD, "keys").await.unwrap(), 3);
+	assert_eq!(storage.list_keys(GUILD, "keys", 1, 10).await.unwrap(), vec![String::from("b"), String::from("c")]);
+	assert_eq!(storage.list_keys(GUILD, "keys", 0, 1).await.unwrap(), vec![String::from("a")]);
+	assert_eq!(storage.keys_with_prefix(GUILD, "keys", "b").await.unwrap(), vec![String::from("b")]);
+	assert_eq!(storage.dump_database(GUILD, "keys").await.unwrap().len(), 3);
+
+	//Only integers and floats are sorted, whatever the text of the other values looks like
+	storage.write_key(GUILD, "keys", "d", &string("100"), None, &LIMITS).await.unwrap();
+	let top: Vec<String> = storage.top_keys(GUILD, "keys", 10, true).await.unwrap().into_iter().map(|(key, _)| key).collect();
+	assert_eq!(top, vec![String::from("a"), String::from("c")]);
+
+	assert_eq!(storage.increment_key(GUILD, "keys", "a", 2, &LIMITS).await.unwrap(), Some(7));
+	assert_eq!(storage.increment_key(GUILD, "keys", "new", -3, &LIMITS).await.unwrap(), Some(-3));
+	assert_eq!(storage.increment_key(GUILD, "keys", "b", 1, &LIMITS).await.unwrap(), None);
+	assert_eq!(storage.increment_key(GUILD, "keys", "c", 1, &LIMITS).await.unwrap(), None);
+	assert_eq!(storage.get_key(GUILD, "keys", "b").await.unwrap(), Some(string("text")));
+
+	assert!(!storage.compare_and_swap(GUILD, "keys", "a", Some(&integer(0)), &integer(1), &LIMITS).await.unwrap());
+	assert!(storage.compare_and_swap(GUILD, "keys", "a", Some(&integer(7)), &integer(1), &LIMITS).await.unwrap());
+	assert!(!storage.compare_and_swap(GUILD, "keys", "a", None, &integer(2), &LIMITS).await.unwrap());
+	assert!(storage.compare_and_swap(GUILD, "keys", "missing", None, &integer(2), &LIMITS).await.unwrap());
+	assert_eq!(storage.get_key(GUILD, "keys", "a").await.unwrap(), Some(integer(1)));
+
+	storage.remove_key(GUILD, "keys", "a").await.unwrap();
+	assert!(!storage.key_exists(GUILD, "keys", "a").await.unwrap());
+	storage.drop_database(GUILD, "keys").await.unwrap();
+	assert_eq!(storage.count_keys(GUILD, "keys").await.unwrap(), 0);
+}
+
+async fn registry(storage: &dyn Storage) {
+	assert!(!storage.database_exists(GUILD, "scores").await.unwrap());
+	assert!(!storage.set_database_description(GUILD, "scores", Some("unused")).await.unwrap());
+	storage.write_key(GUILD, "scores", "a", &integer(1), None, &LIMITS).await.unwrap();
+	storage.write_key(GUILD, "scores", "b", &integer(2), Some(unix_now() - 10), &LIMITS).await.unwrap();
+	storage.write_key(GUILD, "names", "a", &string("a"), None, &LIMITS).await.unwrap();
+	assert!(storage.database_exists(GUILD, "scores").await.unwrap());
+	assert!(!storage.database_exists(OTHER_GUILD, "scores").await.unwrap());
+	assert!(storage.set_database_description(GUILD, "scores", Some("Points per member")).await.unwrap());
+
+	let databases = storage.list_databases(GUILD).await.unwrap();
+	let names: Vec<&str> = databases.iter().map(|database| database.name.as_str()).collect();
+	assert_eq!(names, vec!["names", "scores"]);
+	//Expired keys aren't counted
+	assert_eq!(databases[1].keys, 1);
+	assert_eq!(databases[1].description.as_deref(), Some("Points per member"));
+	assert!(databases[1].created_at > 0);
+
+	//Clearing keeps the database and its description
+	storage.clear_database(GUILD, "scores").await.unwrap();
+	assert!(storage.database_exists(GUILD, "scores").await.unwrap());
+	assert_eq!(storage.count_keys(GUILD, "scores").await.unwrap(), 0);
+	assert_eq!(storage.list_databases(GUILD).await.unwrap()[1].description.as_deref(), Some("Points per member"));
+	assert_eq!(storage.count_keys(GUILD, "names").await.unwrap(), 1);
+
+	//Dropping removes both, the next write registers it again without the description
+	storage.drop_database(GUILD, "scores").await.unwrap();
+	assert!(!storage.database_exists(GUILD, "scores").await.unwrap());
+	storage.write_key(GUILD, "scores", "a", &integer(1), None, &LIMITS).await.unwrap();
+	assert_eq!(storage.list_databases(GUILD).await.unwrap()[1].description, None);
+	storage.drop_database(GUILD, "scores").await.unwrap();
+	storage.drop_database(GUILD, "names").await.unwrap();
+	assert!(storage.list_databases(GUILD).await.unwrap().is_empty());
+}
+
+async fn expiry(storage: &dyn Storage) {
+	let past = unix_now() - 10;
+	let future = unix_now() + 1_000;
+	storage.write_key(GUILD, "expiry", "gone", &integer(5), Some(past), &LIMITS).await.unwrap();
+	storage.write_key(GUILD, "expiry", "kept", &integer(5), Some(future), &LIMITS).await.unwrap();
+	assert_eq!(storage.get_key(GUILD, "expiry", "gone").await.unwrap(), None);
+	assert!(!storage.key_exists(GUILD, "expiry", "gone").await.unwrap());
+	assert_eq!(storage.count_keys(GUILD, "expiry").await.unwrap(), 1);
+	assert_eq!(storage.list_keys(GUILD, "expiry", 0, 10).await.unwrap(), vec![String::from("kept")]);
+	assert_eq!(storage.top_keys(GUILD, "expiry", 10, true).await.unwrap().len(), 1);
+	assert_eq!(storage.usage(GUILD).await.unwrap().keys, 1);
+	assert!(!storage.set_key_expiry(GUILD, "expiry", "gone", None).await.unwrap());
+	assert!(storage.set_key_expiry(GUILD, "expiry", "kept", None).await.unwrap());
+
+	//Writes treat an expired key as missing
+	assert_eq!(storage.increment_key(GUILD, "expiry", "gone", 1, &LIMITS).await.unwrap(), Some(1));
+	storage.write_key(GUILD, "expiry", "gone", &string("text"), Some(past), &LIMITS).await.unwrap();
+	assert!(storage.compare_and_swap(GUILD, "expiry", "gone", None, &integer(2), &LIMITS).await.unwrap());
+	assert_eq!(storage.get_key(GUILD, "expiry", "gone").await.unwrap(), Some(integer(2)));
+
+	storage.write_key(GUILD, "expiry", "gone", &integer(1), Some(past), &LIMITS).await.unwrap();
+	assert_eq!(storage.purge_expired_keys(unix_now()).await.unwrap(), 1);
+	assert_eq!(storage.purge_expired_keys(unix_now()).await.unwrap(), 0);
+	storage.drop_database(GUILD, "expiry").await.unwrap();
+}
+
+async fn quotas(storage: &dyn Storage) {
+	let limits = QuotaLimits {
+		max_keys: 2,
+		max_total_bytes: 1_000,
+		max_value_size: 10,
+		max_databases: 1,
+		max_handlers: 1,
+	};
+	storage.write_key(OTHER_GUILD, "quota", "a", &string("a"), None, &limits).await.unwrap();
+	storage.write_key(OTHER_GUILD, "quota", "b", &string("b"), None, &limits).await.unwrap();
+	//Replacing a key doesn't add one
+	storage.write_key(OTHER_GUILD, "quota", "b", &string("c"), None, &limits).await.unwrap();
+	assert!(matches!(storage.write_key(OTHER_GUILD, "quota", "c", &string("c"), None, &limits).await, Err(QuotaError::TooManyKeys(2))));
+	assert!(matches!(storage.increment_key(OTHER_GUILD, "quota", "c", 1, &limits).await, Err(QuotaError::TooManyKeys(2))));
+	assert!(matches!(storage.compare_and_swap(OTHER_GUILD, "quota", "c", None, &string("c"), &limits).await, Err(QuotaError::TooManyKeys(2))));
+	assert!(matches!(storage.write_key(OTHER_GUILD, "quota", "a", &string("too long for it"), None, &limits).await, Err(QuotaError::ValueTooLarge { .. })));
+	//Refused writes are rolled back along with registering their database
+	assert!(matches!(storage.write_key(OTHER_GUILD, "other", "a", &string("a"), None, &limits).await, Err(QuotaError::TooManyKeys(2))));
+	assert!(!storage.database_exists(OTHER_GUILD, "other").await.unwrap());
+	assert!(!storage.key_exists(OTHER_GUILD, "quota", "c").await.unwrap());
+	let usage = storage.usage(OTHER_GUILD).await.unwrap();
+	assert_eq!((usage.keys, usage.bytes, usage.databases), (2, 4, 1));
+	storage.drop_database(OTHER_GUILD, "quota").await.unwrap();
+}
+
+async fn deletions(storage: &dyn Storage) {
+	assert_eq!(storage.next_deletion_time().await.unwrap(), None);
+	storage.schedule_deletion("1", "2", 200).await.unwrap();
+	storage.schedule_deletion("1", "3", 100).await.unwrap();
+	assert_eq!(storage.next_deletion_time().await.unwrap(), Some(100));
+	let due = storage.due_deletions(150).await.unwrap();
+	assert_eq!(due.len(), 1);
+	assert_eq!(due[0].message_id, "3");
+	storage.remove_deletion(due[0].id).await.unwrap();
+	assert_eq!(storage.next_deletion_time().await.unwrap(), Some(200));
+}
+
+async fn interactions(storage: &dyn Storage) {
+	storage.set_component_handler(GUILD, "vote", "vote code", &LIMITS).await.unwrap();
+	storage.set_component_handler(GUILD, "other", "other code", &LIMITS).await.unwrap();
+	assert_eq!(storage.find_component_handler(GUILD, "vote").await.unwrap(), Some(String::from("vote code")));
+	assert_eq!(storage.find_component_handler(GUILD, "missing").await.unwrap(), None);
+	//Data after the first `:` falls back to the handler of the shared ID, other prefixes don't match
+	assert_eq!(storage.find_component_handler(GUILD, "vote:yes").await.unwrap(), Some(String::from("vote code")));
+	assert_eq!(storage.find_component_handler(GUILD, "vote:yes:2").await.unwrap(), Some(String::from("vote code")));
+	assert_eq!(storage.find_component_handler(GUILD, "voter").await.unwrap(), None);
+	assert_eq!(storage.find_component_handler(GUILD, "vo").await.unwrap(), None);
+	storage.set_component_handler(GUILD, "vote:no", "no code", &LIMITS).await.unwrap();
+	assert_eq!(storage.find_component_handler(GUILD, "vote:no").await.unwrap(), Some(String::from("no code")));
+	assert_eq!(storage.find_component_handler(OTHER_GUILD, "vote").await.unwrap(), None);
+
+	let command = CustomSlashCommand {
+		name: String::from("roll"),
+		description: String::from("Rolls a die"),
+		options: String::from("[]"),
+		code: String::from("code"),
+	};
+	storage.set_slash_command(GUILD, &command).await.unwrap();
+	assert_eq!(storage.get_slash_command(GUILD, "roll").await.unwrap().map(|found| found.description), Some(command.description.clone()));
+	assert_eq!(storage.list_slash_commands(GUILD).await.unwrap().len(), 1);
+	assert!(storage.remove_slash_command(GUILD, "roll").await.unwrap());
+	assert!(!storage.remove_slash_command(GUILD, "roll").await.unwrap());
+
+	let modal = StoredModal {
+		modal_id: String::from("form"),
+		title: String::from("Form"),
+		inputs: String::from("[]"),
+		code: String::from("code"),
+	};
+	storage.set_modal(GUILD, &modal, &LIMITS).await.unwrap();
+	assert_eq!(storage.get_modal(GUILD, "form").await.unwrap().map(|found| found.title), Some(modal.title.clone()));
+	assert!(storage.get_modal(OTHER_GUILD, "form").await.unwrap().is_none());
+
+	//Handlers and modals count together, replacing one doesn't add one
+	let limits = QuotaLimits { max_handlers: 4, ..LIMITS };
+	storage.set_component_handler(GUILD, "vote", "new vote code", &limits).await.unwrap();
+	assert!(matches!(storage.set_component_handler(GUILD, "extra", "code", &limits).await, Err(QuotaError::TooManyHandlers(4))));
+	let extra = StoredModal { modal_id: String::from("extra"), ..modal };
+	assert!(matches!(storage.set_modal(GUILD, &extra, &limits).await, Err(QuotaError::TooManyHandlers(4))));
+	assert_eq!(storage.find_component_handler(GUILD, "extra").await.unwrap(), None);
+	storage.set_modal(OTHER_GUILD, &extra, &limits).await.unwrap();
+
+	assert_eq!(storage.purge_old_handlers(unix_now() - 60).await.unwrap(), 0);
+	assert_eq!(storage.purge_old_handlers(unix_now() + 1).await.unwrap(), 5);
+	assert_eq!(storage.find_component_handler(GUILD, "vote").await.unwrap(), None);
+	assert!(storage.get_modal(GUILD, "form").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn sqlite() {
+	exercise(connect("sqlite::memory:").await.unwrap()).await;
+}
+
+/// Runs when `TEST_POSTGRES_URL` points to an empty database, like it does in CI
+#[tokio::test]
+async fn postgres() {
+	match std::env::var("TEST_POSTGRES_URL") {
+		Ok(database_url) => {
+			exercise(connect(&database_url).await.unwrap()).await;
+		}
+		Err(_) => {
+			eprintln!("TEST_POSTGRES_URL isn't set, skipping");
+		}
+	}
+}
@@ -7,11 +7,31 @@ use crate::databases::{
 pub struct Database;
 
 impl TypeMapKey for Database {
-	type Value = sqlx::sqlite::SqlitePool;
+	type Value = std::sync::Arc<dyn crate::storage::Storage>;
+}
+
+/// Storage used by scripts, see [crate::databases::ScriptStorage]
+pub struct ScriptDatabase;
+
+impl TypeMapKey for ScriptDatabase {
+	type Value = std::sync::Arc<crate::databases::ScriptStorage>;
 }
 
 pub struct KeyList;
 
 impl TypeMapKey for KeyList {
 	type Value = std::collections::HashMap<String, Box<dyn yttrium_key_base::Key<SqlDatabaseManager, SqlDatabase> + Sync + Send>>;
-}
\ No newline at end of file
+}
+
+pub struct BotConfig;
+
+impl TypeMapKey for BotConfig {
+	type Value = crate::config::Config;
+}
+
+/// Wakes up the task processing the deletion queue, see [crate::deletions]
+pub struct DeletionQueue;
+
+impl TypeMapKey for DeletionQueue {
+	type Value = std::sync::Arc<tokio::sync::Notify>;
+}
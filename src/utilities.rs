@@ -1,9 +1,14 @@
 use serenity::{
 	model::{
 		channel::Message,
+		guild::{
+			Member,
+			PremiumTier,
+		},
 		id::{
 			RoleId,
 			ChannelId,
+			GuildId,
 		},
 	},
 	prelude::Context,
@@ -14,124 +19,131 @@ use serenity::{
 		macros::{hook, check}
 	}
 };
+use std::sync::Arc;
 use yttrium::ResultAndWarnings;
 use yttrium_key_base::databases::{DatabaseManager, Database};
+use yttrium_key_base::environment::{Environment, events::EventType};
+use crate::storage::{Storage, StorageResult};
+use crate::types::{
+	BotConfig,
+	DeletionQueue,
+	KeyList,
+	ScriptDatabase,
+};
+use crate::databases;
+use crate::deletions;
+use crate::output::{self, Target};
+
+/// Every event that code can be attached to, properly capitalized
+pub const EVENT_NAMES: &[&str] = &[
+	"MemberJoin",
+	"MemberLeave",
+	"MemberUpdate",
+	"RoleCreate",
+	"RoleUpdate",
+	"RoleDelete",
+	"ChannelCreate",
+	"ChannelDelete",
+	"ChannelUpdate",
+	"GuildUpdate",
+	"VoiceUpdate",
+	"VoiceJoin",
+	"VoiceLeave",
+	"VoiceMove",
+	"VoiceMute",
+	"VoiceDeafen",
+	"VoiceStream",
+	"ReactionAdd",
+	"ReactionRemove",
+	"ComponentInteraction",
+];
 
 /// Returns a properly capitalized event name, or [None] if the original string was empty or didn't contain an event name
-pub fn proper_event_name(original: &str) -> Option<&str> {
-	match original.to_ascii_lowercase().as_ref() {
-		"memberjoin" => {
-			return Some("MemberJoin");
-		}
-		"memberleave" => {
-			return Some("MemberLeave");
-		}
-		"memberupdate" => {
-			return Some("MemberUpdate");
-		}
-		"rolecreate" => {
-			return Some("RoleCreate");
-		}
-		"roleupdate" => {
-			return Some("RoleUpdate");
-		}
-		"roledelete" => {
-			return Some("RoleDelete");
-		}
-		"channelcreate" => {
-			return Some("ChannelCreate");
-		}
-		"channeldelete" => {
-			return Some("ChannelDelete");
-		}
-		"channelupdate" => {
-			return Some("ChannelUpdate");
-		}
-		"guildupdate" => {
-			return Some("GuildUpdate");
-		}
-		"voiceupdate" => {
-			return Some("VoiceUpdate");
-		}
-		"reactionadd" => {
-			return Some("ReactionAdd");
-		}
-		"reactionremove" => {
-			return Some("ReactionRemove");
-		}
-		_ => {
-			return None;
-		}
-	};
+pub fn proper_event_name(original: &str) -> Option<&'static str> {
+	return EVENT_NAMES.iter().find(|name| name.eq_ignore_ascii_case(original)).copied();
 }
 
 #[hook]
-pub async fn get_guild_prefix(guild_id: &str, database: &sqlx::SqlitePool) -> String {
-	let query = sqlx::query!("SELECT prefix FROM config WHERE guild_id = ?", guild_id);
-	match query.fetch_optional(database).await {
+pub async fn get_guild_prefix(guild_id: &str, default_prefix: &str, database: &Arc<dyn Storage>) -> String {
+	match database.get_prefix(guild_id).await {
 		Ok(result) => {
 			match result {
-				Some(result) => {
-					match result.prefix {
-						Some(prefix) => {
-							return prefix;
-						}
-						None => {
-							return String::from(".");
-						}
-					}
+				Some(prefix) => {
+					return prefix;
 				}
 				None => {
-					return String::from(".");
+					return String::from(default_prefix);
 				}
 			}
 		}
 		Err(error) => {
-			eprintln!("get_guild_prefix: Error: `{}`", error);
+			tracing::error!("get_guild_prefix: Error: `{}`", error);
 			return String::new();
 		}
 	}
 }
 
-pub async fn set_guild_prefix(guild_id: &str, new_prefix: &str, database: &sqlx::SqlitePool) -> bool {
-	let query = sqlx::query!("INSERT INTO config (guild_id, prefix) VALUES (?, ?) ON CONFLICT (guild_id) DO UPDATE SET prefix = ?", guild_id, new_prefix, new_prefix);
-	let result = query.execute(database).await.unwrap();
-	return result.rows_affected() == 1;
+pub async fn set_guild_prefix(guild_id: &str, new_prefix: &str, database: &Arc<dyn Storage>) -> StorageResult<bool> {
+	return database.set_prefix(guild_id, new_prefix).await;
 }
 
 #[check]
 pub async fn is_guild_admin(context: &Context, message: &Message, _args: &mut Args, _command_options: &CommandOptions) -> Result<(), Reason> {
-	let permissions = message.member(context).await.unwrap().permissions(context).await.unwrap();
+	match message.member(context).await {
+		Ok(member) => {
+			return check_guild_admin(context, message.guild_id.unwrap(), &member).await;
+		}
+		Err(error) => {
+			return Err(Reason::UserAndLog {
+				user: String::from("Could not check your permissions"),
+				log: format!("is_guild_admin: Could not get the member: `{}`", error),
+			});
+		}
+	}
+}
+
+/// Checks if the member can manage the bot in the guild, shared by prefix and slash commands
+pub async fn check_guild_admin(context: &Context, guild_id: GuildId, member: &Member) -> Result<(), Reason> {
+	let permissions;
+	match member.permissions(context).await {
+		Ok(found) => {
+			permissions = found;
+		}
+		Err(error) => {
+			return Err(Reason::UserAndLog {
+				user: String::from("Could not check your permissions"),
+				log: format!("is_guild_admin: Could not get the permissions of `{}` on guild `{}`: `{}`", member.user.id, guild_id, error),
+			});
+		}
+	}
 	if permissions.administrator() {
 		return Ok(());
 	}
-	let guild_id = message.guild_id.unwrap().to_string();
-	let query  = sqlx::query!("SELECT admin_role FROM config WHERE guild_id = ?", guild_id);
+	let guild_id = guild_id.to_string();
 	let lock = context.data.read().await;
 	let db = lock.get::<crate::types::Database>().unwrap();
-	match query.fetch_optional(db).await {
+	match db.get_admin_role(&guild_id).await {
 		Ok(result) => {
 			match result {
-				Some(result) => {
+				Some(admin_role) => {
 					//Some admin role was set, check if the user has it
-					match result.admin_role {
-						Some(admin_role) => {
-							let role_id = RoleId::from(admin_role.parse::<u64>().unwrap());
-							if message.member.as_ref().unwrap().roles.contains(&role_id) {
-								return Ok(());
-							} else {
-								return Err(Reason::User(String::from("You do not have the required role")));
-							}
+					let role_id;
+					match admin_role.parse::<u64>() {
+						Ok(id) => {
+							role_id = RoleId::from(id);
 						}
-						None => {
-							//No admin role set in the config, check for server management permissions
-							if permissions.manage_guild() {
-								return Ok(());
-							} else {
-								return Err(Reason::User(String::from("You do not have the Manage Guild permission")));
-							}
+						Err(_) => {
+							return Err(Reason::UserAndLog {
+								user: String::from("The admin role of this server is invalid, set it again"),
+								log: format!("is_guild_admin: Invalid admin role `{}` on guild `{}`", admin_role, guild_id),
+							});
 						}
 					}
+					if member.roles.contains(&role_id) {
+						return Ok(());
+					} else {
+						return Err(Reason::User(String::from("You do not have the required role")));
+					}
 				}
 				None => {
 					//No admin role set in the config, check for server management permissions
@@ -152,19 +164,87 @@ pub async fn is_guild_admin(context: &Context, message: &Message, _args: &mut Ar
 	}
 }
 
-pub async fn set_guild_admin(guild_id: &str, new_admin_role: Option<String>, database: &sqlx::SqlitePool) -> bool {
-	let query = sqlx::query!("INSERT INTO config (guild_id, admin_role) VALUES (?, ?) ON CONFLICT (guild_id) DO UPDATE SET admin_role = ?", guild_id, new_admin_role, new_admin_role);
-	let result = query.execute(database).await.unwrap();
-	return result.rows_affected() == 1;
+pub async fn set_guild_admin(guild_id: &str, new_admin_role: Option<String>, database: &Arc<dyn Storage>) -> StorageResult<bool> {
+	return database.set_admin_role(guild_id, new_admin_role.as_deref()).await;
+}
+
+pub async fn set_guild_error_channel(guild_id: &str, new_channel: Option<String>, database: &Arc<dyn Storage>) -> StorageResult<bool> {
+	return database.set_error_channel(guild_id, new_channel.as_deref()).await;
 }
 
-pub async fn set_guild_error_channel(guild_id: &str, new_channel: Option<String>, database: &sqlx::SqlitePool) -> bool {
-	let query = sqlx::query!("INSERT INTO config (guild_id, error_channel) VALUES (?, ?) ON CONFLICT (guild_id) DO UPDATE SET error_channel = ?", guild_id, new_channel, new_channel);
-	let result = query.execute(database).await.unwrap();
-	return result.rows_affected() == 1;
+/// Sends an error caused by a script to the guild's error channel, or logs it if the guild doesn't have one
+pub async fn report_error(context: &Context, guild_id: GuildId, error: &str) {
+	let lock = context.data.read().await;
+	let db = lock.get::<crate::types::Database>().unwrap();
+	let error_channel = db.get_error_channel(&guild_id.to_string()).await;
+	drop(lock);
+	match error_channel {
+		Ok(Some(channel)) => {
+			if let Ok(channel) = channel.parse::<u64>() {
+				//Errors can contain text from scripts, which must not ping anyone
+				let sent = ChannelId(channel).send_message(context, |message| {
+					return message.content(error).allowed_mentions(|mentions| mentions.empty_parse());
+				}).await;
+				if let Err(send_error) = sent {
+					tracing::warn!("Could not report `{}` on guild `{}`: `{}`", error, guild_id, send_error);
+				}
+				return;
+			}
+			tracing::warn!("Invalid error channel `{}` on guild `{}`: `{}`", channel, guild_id, error);
+		}
+		Ok(None) => {
+			tracing::warn!("Error on guild `{}`: `{}`", guild_id, error);
+		}
+		Err(db_error) => {
+			tracing::error!("report_error: DB error: `{}`, while reporting `{}` on guild `{}`", db_error, error, guild_id);
+		}
+	}
 }
 
-pub async fn send_result<DB: Database, Manager: DatabaseManager<DB>>(context: &Context, result: ResultAndWarnings<'_, Manager, DB>) {
+/// How many bytes of files a message in the guild can have, the configured limit or Discord's if it's lower
+pub async fn upload_size_limit(context: &Context, guild_id: GuildId) -> u64 {
+	let max_attachment_size = context.data.read().await.get::<BotConfig>().unwrap().max_attachment_size;
+	match guild_id.to_guild_cached(&context.cache).await {
+		Some(guild) => {
+			return std::cmp::min(max_attachment_size, output::guild_upload_limit(guild.premium_tier));
+		}
+		None => {
+			return std::cmp::min(max_attachment_size, output::guild_upload_limit(PremiumTier::Tier0));
+		}
+	}
+}
+
+/// Interprets `code` and sends its output, `on_error` is given the error if it couldn't be interpreted
+/// The script runs on a blocking thread, see [databases::run_blocking]
+pub async fn run_script<E, R>(context: &Context, code: String, event: EventType, guild_id: GuildId, origin: Option<Message>, on_error: E)
+where
+	E: FnOnce(Context, yttrium::errors_and_warns::Error) -> R + Send + 'static,
+	R: std::future::Future<Output = ()>,
+{
+	let context = context.clone();
+	let finished = databases::run_blocking(move || async move {
+		let lock = context.data.read().await;
+		let db_manager = databases::SqlDatabaseManager::new(guild_id, lock.get::<ScriptDatabase>().unwrap(), lock.get::<BotConfig>().unwrap().quotas.for_guild(guild_id));
+		let environment = Environment::new(event, guild_id, &context, db_manager);
+		let keys = lock.get::<KeyList>().unwrap();
+		let output = yttrium::interpret_string(code, keys, environment).await;
+		match output {
+			Ok(result) => {
+				send_result(&context, result, guild_id, origin.as_ref()).await;
+			}
+			Err(error) => {
+				on_error(context.clone(), error).await;
+			}
+		}
+	}).await;
+	if let Err(error) = finished {
+		tracing::error!("A script on guild `{}` did not finish: `{}`", guild_id, error);
+	}
+}
+
+/// Sends the output of a script to its target
+/// `origin` is the message that started the script, [None] for events
+pub async fn send_result<DB: Database, Manager: DatabaseManager<DB>>(context: &Context, result: ResultAndWarnings<'_, Manager, DB>, guild_id: GuildId, origin: Option<&Message>) {
 	let mut output = String::new();
 	if let Some(warnings) = result.warnings {
 		for warning in warnings {
@@ -180,33 +260,152 @@ pub async fn send_result<DB: Database, Manager: DatabaseManager<DB>>(context: &C
 		return;
 	}
 	let result = result.unwrap();
+	let lock = context.data.read().await;
+	let file_threshold = lock.get::<BotConfig>().unwrap().output_file_threshold;
+	let settings = lock.get::<crate::types::Database>().unwrap().get_output_settings(&guild_id.to_string()).await;
+	drop(lock);
+	let settings = settings.unwrap_or_else(|error| {
+		tracing::error!("send_result: DB error: `{}`", error);
+		return crate::storage::OutputSettings::default();
+	});
+	let mut target;
+	match Target::resolve(context, guild_id, &result.environment.target, origin).await {
+		Ok(resolved) => {
+			target = resolved;
+		}
+		Err(error) => {
+			report_error(context, guild_id, &format!("Invalid output target `{}`: {}", result.environment.target, error)).await;
+			return;
+		}
+	}
+	if let (Target::Channel(channel), Some(origin)) = (target, origin) {
+		if settings.reply_to_trigger && channel == origin.channel_id {
+			target = Target::Reply(channel, origin.id);
+		}
+	}
+	let options = output::SendOptions { file_threshold, reply_to: target.reply_to(), settings };
 	let channel;
-	match result.environment.target.parse::<u64>() {
-		Ok(value) => {
-			channel = ChannelId::from(value)
+	match target.channel(context).await {
+		Ok(resolved) => {
+			channel = resolved;
 		}
-		Err(_) => {
-			// TODO fix this
+		Err(error) => {
+			report_error(context, guild_id, &format!("Could not open {}: {}", target, error)).await;
 			return;
 		}
 	}
 	output.push_str(&result.message);
-	let mut message = None;
-	if !output.is_empty() {
-		message = Some(channel.say(context, &output).await.unwrap());
+	let lock = context.data.read().await;
+	let storage = lock.get::<crate::types::Database>().unwrap().clone();
+	let limits = lock.get::<BotConfig>().unwrap().quotas.for_guild(guild_id);
+	drop(lock);
+	//Modals are stored first, so that buttons in the same output can open them
+	if !result.environment.modals.is_empty() {
+		if let Err(error) = crate::components::store_modals(result.environment.modals, &guild_id.to_string(), &storage, &limits).await {
+			report_error(context, guild_id, &format!("Invalid modal: {}", error)).await;
+		}
+	}
+	let mut components = None;
+	let handlers = crate::components::handlers(&result.environment.components);
+	if !result.environment.components.is_empty() {
+		match crate::components::build(result.environment.components) {
+			Ok(built) => {
+				components = Some(built);
+			}
+			Err(error) => {
+				report_error(context, guild_id, &format!("Invalid message components: {}", error)).await;
+			}
+		}
+	}
+	let size_limit = upload_size_limit(context, guild_id).await;
+	let mut files = Vec::new();
+	for attachment in result.environment.attachments {
+		match output::OutputFile::new(attachment, size_limit) {
+			Ok(file) => {
+				files.push(file);
+			}
+			Err(error) => {
+				report_error(context, guild_id, &format!("Could not attach a file: {}", error)).await;
+			}
+		}
+	}
+	let embeds = result.environment.embeds;
+	//Components go on the last message, which is the rich one if there are embeds or files
+	let rich = !files.is_empty() || !embeds.is_empty();
+	if components.is_some() && !rich && output.is_empty() {
+		report_error(context, guild_id, "Message components need text, an embed or a file to be attached to").await;
+		components = None;
+	}
+	let mut messages = Vec::new();
+	let mut components_sent = false;
+	if let Target::Update(channel, message) = target {
+		if !files.is_empty() {
+			report_error(context, guild_id, "Files can't be added to a message when updating it").await;
+		}
+		let has_components = components.is_some();
+		match output::update_message(context, channel, message, &output, embeds, components).await {
+			Ok(updated) => {
+				messages.push(updated);
+				components_sent = has_components;
+			}
+			Err(error) => {
+				report_error(context, guild_id, &format!("Could not update the message in <#{}>: {}", channel, error)).await;
+			}
+		}
+	} else {
+		if !output.is_empty() {
+			let text_components;
+			if rich {
+				text_components = None;
+			} else {
+				text_components = components.take();
+			}
+			let has_components = text_components.is_some();
+			match output::send_text(context, channel, &output, text_components, &options).await {
+				Ok(sent) => {
+					messages = sent;
+					components_sent = has_components;
+				}
+				Err(error) => {
+					report_error(context, guild_id, &format!("Could not send the output to {}: {}", target, error)).await;
+					return;
+				}
+			}
+		}
+		if rich {
+			let has_components = components.is_some();
+			match output::send_rich(context, channel, files, embeds, components, &options, messages.is_empty(), size_limit).await {
+				Ok(sent) => {
+					messages.extend(sent);
+					components_sent = components_sent || has_components;
+				}
+				Err(error) => {
+					report_error(context, guild_id, &format!("Could not send the embeds or files to {}: {}", target, error)).await;
+				}
+			}
+		}
 	}
-	if let Some(embed) = result.environment.embed {
-		message = Some(channel.send_message(context, |message| {
-			return message.set_embed(embed);
-		}).await.unwrap());
+	if components_sent {
+		if let Err(error) = crate::components::store_handlers(handlers, &guild_id.to_string(), &storage, &limits).await {
+			report_error(context, guild_id, &format!("Message components were sent, but {}", error)).await;
+		}
 	}
-	if let Some(message) = message {
+	//Reactions go on the last message, deletion removes every message of the output
+	if let Some(message) = messages.last() {
 		for reaction in result.environment.reactions_to_add {
-			message.react(context, reaction).await.unwrap();
+			if let Err(error) = message.react(context, reaction).await {
+				report_error(context, guild_id, &format!("Could not add a reaction in {}: {}", target, error)).await;
+			}
 		}
-		if let Some(duration) = result.environment.delete_option {
-			tokio::time::sleep(duration).await;
-			message.delete(context).await.unwrap();
+	}
+	if let Some(duration) = result.environment.delete_option {
+		let lock = context.data.read().await;
+		let storage = lock.get::<crate::types::Database>().unwrap();
+		let wake_up = lock.get::<DeletionQueue>().unwrap();
+		for message in messages {
+			if let Err(error) = deletions::schedule(storage, wake_up, &message, duration).await {
+				tracing::warn!("Could not schedule the deletion of a message: `{}`", error);
+			}
 		}
 	}
 }